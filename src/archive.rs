@@ -0,0 +1,145 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{LazyLock, PoisonError, RwLock},
+};
+
+use flate2::read::DeflateDecoder;
+use zip::{result::ZipError, CompressionMethod, ZipArchive};
+
+use crate::{
+    error::{Error, Result},
+    format::extension,
+};
+
+pub fn is_archive(path: &Path) -> bool {
+    extension(path).as_deref() == Some("zip")
+}
+
+/// The entries of an archive are given as the path of the archive followed by the name of the
+/// entry, e.g. `export.zip/activities/123.gpx.gz`. Split such a path into the archive and the name
+pub fn split_archive_path(path: &Path) -> Option<(&Path, String)> {
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| is_archive(ancestor) && ancestor.is_file())?;
+    let entry = path.strip_prefix(archive).ok()?;
+    let name: Vec<String> = entry
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    Some((archive, name.join("/")))
+}
+
+/// Where the data of an entry starts in the archive file, its size and how it is compressed
+#[derive(Clone, Copy, Debug)]
+struct EntryLocation {
+    start: u64,
+    size: u64,
+    compression: CompressionMethod,
+}
+
+/// Locations of the entries of the archives already listed, by the path of the entry, so that
+/// opening an entry does not read the central directory of the archive again
+static ENTRIES: LazyLock<RwLock<HashMap<PathBuf, EntryLocation>>> = LazyLock::new(Default::default);
+
+/// Paths of all the files of the archive, folders excluded. The central directory of the archive is
+/// only read here, the location of every entry is kept for opening it later
+pub fn list_archive_entries(archive: &Path) -> Result<Vec<PathBuf>> {
+    let mut zip = open_archive(archive)?;
+    let mut entries = Vec::new();
+    for index in 0..zip.len() {
+        let entry = zip
+            .by_index_raw(index)
+            .map_err(|err| archive_error(archive, err))?;
+        if entry.is_dir() {
+            continue;
+        }
+        let location = EntryLocation {
+            start: entry.data_start(),
+            size: entry.compressed_size(),
+            compression: entry.compression(),
+        };
+        entries.push((archive.join(entry.name()), location));
+    }
+
+    let paths = entries.iter().map(|(path, _)| path.clone()).collect();
+    ENTRIES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .extend(entries);
+    Ok(paths)
+}
+
+/// Open the entry for reading, still compressed if it is a .gz, .bz2 or .zst file. Its data is
+/// read straight from the archive file and inflated while it is read, instead of being loaded in
+/// memory. The archive is only listed again when it was not listed before, and every call opens
+/// the file again, so the entries can be read from several threads
+pub fn open_archive_entry(archive: &Path, name: &str) -> Result<Box<dyn Read>> {
+    let path = archive.join(name);
+    let find = |path: &Path| {
+        let entries = ENTRIES.read().unwrap_or_else(PoisonError::into_inner);
+        entries.get(path).copied()
+    };
+    let location = match find(&path) {
+        Some(location) => location,
+        None => {
+            list_archive_entries(archive)?;
+            find(&path).ok_or_else(|| archive_error(archive, ZipError::FileNotFound))?
+        }
+    };
+
+    let mut file = File::open(archive).map_err(|err| Error::io(archive, err))?;
+    file.seek(SeekFrom::Start(location.start))
+        .map_err(|err| Error::io(archive, err))?;
+    let data = BufReader::new(file).take(location.size);
+    match location.compression {
+        CompressionMethod::Stored => Ok(Box::new(data)),
+        CompressionMethod::Deflated => Ok(Box::new(DeflateDecoder::new(data))),
+        method => Err(Error::Archive {
+            path,
+            message: format!("unsupported compression method {method}"),
+        }),
+    }
+}
+
+/// A .kmz file is a zip archive of a KML document, usually `doc.kml`, and the images it uses.
+/// Read the first .kml entry of the archive
+pub fn read_kmz_document<R: Read>(mut kmz: R, path: &Path) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    kmz.read_to_end(&mut content)
+        .map_err(|err| Error::io(path, err))?;
+    let mut zip = ZipArchive::new(Cursor::new(content)).map_err(|err| archive_error(path, err))?;
+
+    for index in 0..zip.len() {
+        let mut entry = zip
+            .by_index(index)
+            .map_err(|err| archive_error(path, err))?;
+        if extension(Path::new(entry.name())).as_deref() != Some("kml") {
+            continue;
+        }
+        let mut document = Vec::new();
+        entry
+            .read_to_end(&mut document)
+            .map_err(|err| Error::io(path, err))?;
+        return Ok(document);
+    }
+    Err(Error::Archive {
+        path: path.to_path_buf(),
+        message: String::from("no .kml document in the archive"),
+    })
+}
+
+fn open_archive(archive: &Path) -> Result<ZipArchive<BufReader<File>>> {
+    let file = File::open(archive).map_err(|err| Error::io(archive, err))?;
+    ZipArchive::new(BufReader::new(file)).map_err(|err| archive_error(archive, err))
+}
+
+fn archive_error(archive: &Path, error: ZipError) -> Error {
+    Error::Archive {
+        path: archive.to_path_buf(),
+        message: error.to_string(),
+    }
+}
@@ -0,0 +1,253 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use geojson::{Feature, GeoJson, Geometry, PolygonType, Value};
+
+use crate::{
+    error::{Error, Result},
+    io::read_track_coordinates,
+    model::{AreaFilter, Coordinate, CorridorFilter, NamedArea, PointKind, PolygonFilter},
+};
+
+/// Load the areas defined in a GeoJSON file. The file may contain a single geometry, a feature or
+/// a feature collection. Every feature with a `Polygon` or `MultiPolygon` geometry is turned into
+/// an area named after its `properties.name`, or after its position in the file if it has none.
+/// The names of the other features are returned as well, as they are skipped.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use gpx_geo_filter::areas::load_geojson_areas;
+///
+/// let (areas, skipped) = load_geojson_areas("regions.geojson").unwrap();
+/// for name in skipped {
+///     eprintln!("Feature '{name}' is not a polygon");
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Fails if the file can not be opened or is not valid GeoJSON, or if a polygon has a ring with
+/// fewer than 3 points or a multipolygon has no polygons.
+pub fn load_geojson_areas<P: AsRef<Path>>(path: P) -> Result<(Vec<NamedArea>, Vec<String>)> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|err| Error::io(path, err))?;
+    let geojson = GeoJson::from_reader(BufReader::new(file)).map_err(|err| Error::GeoJson {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })?;
+
+    parse_geojson_areas(geojson, path)
+}
+
+/// Build a corridor around the track points of the reference .gpx file, or around its route points
+/// when it has no track, including all the points closer than `buffer_m` meters to the route
+///
+/// # Errors
+///
+/// Fails if the file can not be opened, is not valid XML or has fewer than 2 points to follow, or
+/// if the buffer is negative.
+pub fn load_gpx_corridor<P: AsRef<Path>>(path: P, buffer_m: f64) -> Result<CorridorFilter> {
+    let path = path.as_ref();
+    let mut route = read_track_coordinates(path, &[PointKind::Track])?;
+    if route.is_empty() {
+        route = read_track_coordinates(path, &[PointKind::Route])?;
+    }
+    if route.len() < 2 {
+        return Err(Error::InvalidValue {
+            path: path.to_path_buf(),
+            value: format!("a route of {} points, at least 2 are needed", route.len()),
+        });
+    }
+
+    CorridorFilter::new(route, buffer_m)
+}
+
+/// Convert the GeoJSON object into the list of areas it contains, and the names of the features
+/// that are not areas
+fn parse_geojson_areas(geojson: GeoJson, path: &Path) -> Result<(Vec<NamedArea>, Vec<String>)> {
+    let features = match geojson {
+        GeoJson::FeatureCollection(collection) => collection.features,
+        GeoJson::Feature(feature) => vec![feature],
+        GeoJson::Geometry(geometry) => vec![Feature::from(geometry)],
+    };
+
+    let mut areas = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, feature) in features.iter().enumerate() {
+        let name = match feature.property("name").and_then(|name| name.as_str()) {
+            Some(name) => String::from(name),
+            None => format!("area-{}", index + 1),
+        };
+
+        match feature.geometry.as_ref().map(geometry_to_area) {
+            Some(Some(Ok(area))) => areas.push(NamedArea { name, area }),
+            Some(Some(Err(message))) => {
+                return Err(Error::GeoJson {
+                    path: path.to_path_buf(),
+                    message: format!("area '{name}' {message}"),
+                })
+            }
+            _ => skipped.push(name),
+        }
+    }
+    Ok((areas, skipped))
+}
+
+/// Only polygons and multipolygons define an area. The error describes why the polygon is invalid
+fn geometry_to_area(geometry: &Geometry) -> Option<std::result::Result<AreaFilter, &'static str>> {
+    match &geometry.value {
+        Value::Polygon(polygon) => Some(to_polygon_filter(polygon).map(AreaFilter::Polygon)),
+        Value::MultiPolygon(polygons) if polygons.is_empty() => Some(Err("has no polygons")),
+        Value::MultiPolygon(polygons) => Some(
+            polygons
+                .iter()
+                .map(to_polygon_filter)
+                .collect::<std::result::Result<_, _>>()
+                .map(AreaFilter::MultiPolygon),
+        ),
+        _ => None,
+    }
+}
+
+/// The first ring of a GeoJSON polygon is the exterior and the rest are holes. Positions are
+/// stored as `[longitude, latitude]`. Every ring needs at least 3 points besides the one closing it
+fn to_polygon_filter(polygon: &PolygonType) -> std::result::Result<PolygonFilter, &'static str> {
+    let mut rings = Vec::new();
+    for ring in polygon {
+        let ring = ring
+            .iter()
+            .map(|position| match position[..] {
+                [longitude, latitude, ..] => Ok(Coordinate::new(latitude as f32, longitude as f32)),
+                _ => Err("has a position without latitude"),
+            })
+            .collect::<std::result::Result<Vec<Coordinate>, _>>()?;
+        let closed = ring.len() > 1 && ring.first() == ring.last();
+        if ring.len() - usize::from(closed) < 3 {
+            return Err("has a ring with fewer than 3 points");
+        }
+        rings.push(ring);
+    }
+
+    if rings.is_empty() {
+        return Err("has no exterior ring");
+    }
+    let exterior = rings.remove(0);
+    Ok(PolygonFilter::new(exterior, rings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_feature_collection() {
+        let geojson: GeoJson = r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "properties": { "name": "Park" },
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [
+                            [[10.0, 40.0], [20.0, 40.0], [20.0, 50.0], [10.0, 50.0], [10.0, 40.0]],
+                            [[12.0, 42.0], [14.0, 42.0], [14.0, 44.0], [12.0, 42.0]]
+                        ]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": {},
+                    "geometry": {
+                        "type": "MultiPolygon",
+                        "coordinates": [
+                            [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]]],
+                            [[[5.0, 5.0], [6.0, 5.0], [6.0, 6.0], [5.0, 5.0]]]
+                        ]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": { "name": "Summit" },
+                    "geometry": { "type": "Point", "coordinates": [11.0, 47.0] }
+                }
+            ]
+        }"#
+        .parse()
+        .unwrap();
+
+        let (areas, skipped) = parse_geojson_areas(geojson, Path::new("areas.geojson")).unwrap();
+
+        assert_eq!(vec![String::from("Summit")], skipped);
+        assert_eq!(2, areas.len());
+        assert_eq!("Park", areas[0].name);
+        assert_eq!("area-2", areas[1].name);
+
+        match &areas[0].area {
+            AreaFilter::Polygon(polygon) => {
+                assert_eq!(Coordinate::new(40.0, 10.0), polygon.exterior[0]);
+                assert_eq!(1, polygon.holes.len());
+            }
+            area => panic!("Unexpected area {:?}", area),
+        }
+        match &areas[1].area {
+            AreaFilter::MultiPolygon(polygons) => assert_eq!(2, polygons.len()),
+            area => panic!("Unexpected area {:?}", area),
+        }
+    }
+
+    #[test]
+    fn parse_single_geometry() {
+        let geojson: GeoJson = r#"{
+            "type": "Polygon",
+            "coordinates": [[[10.0, 40.0], [20.0, 40.0], [20.0, 50.0], [10.0, 40.0]]]
+        }"#
+        .parse()
+        .unwrap();
+
+        let (areas, _) = parse_geojson_areas(geojson, Path::new("areas.geojson")).unwrap();
+
+        assert_eq!(1, areas.len());
+        assert_eq!("area-1", areas[0].name);
+    }
+
+    #[test]
+    fn reject_corridor_without_route() {
+        let path = std::env::temp_dir().join("gpx-geo-filter-single-point.gpx");
+        let content = r#"<gpx><trk><trkseg><trkpt lat="49.4" lon="11.0"/></trkseg></trk></gpx>"#;
+        std::fs::write(&path, content).unwrap();
+
+        assert!(matches!(
+            load_gpx_corridor(&path, 50.0),
+            Err(Error::InvalidValue { .. })
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reject_degenerate_polygons() {
+        let parse = |geometry: &str| {
+            let geojson: GeoJson = geometry.parse().unwrap();
+            parse_geojson_areas(geojson, Path::new("areas.geojson"))
+        };
+
+        let line =
+            r#"{"type": "Polygon", "coordinates": [[[10.0, 40.0], [20.0, 40.0], [10.0, 40.0]]]}"#;
+        let hole = r#"{"type": "Polygon", "coordinates": [
+            [[10.0, 40.0], [20.0, 40.0], [20.0, 50.0], [10.0, 40.0]],
+            []
+        ]}"#;
+        for geometry in [
+            line,
+            hole,
+            r#"{"type": "Polygon", "coordinates": []}"#,
+            r#"{"type": "MultiPolygon", "coordinates": []}"#,
+        ] {
+            assert!(
+                matches!(parse(geometry), Err(Error::GeoJson { .. })),
+                "{geometry}"
+            );
+        }
+    }
+}
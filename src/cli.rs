@@ -1,70 +1,548 @@
-use std::path::PathBuf;
+use std::{collections::HashSet, path::PathBuf};
 
-use clap::Parser;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use glob::Pattern;
+use regex::{Regex, RegexBuilder};
 
-use crate::config::GpxGeoFilterConfig;
+use crate::{
+    areas::{load_geojson_areas, load_gpx_corridor},
+    config::GpxGeoFilterConfig,
+    error::Error,
+    model::{
+        ActivityType, AreaExpression, CircleFilter, Coordinate, FolderScan, MatchMode, NamedArea,
+        PointKind, SquaredFilter, SymlinkPolicy, TrackConditions,
+    },
+    output::OutputFormat,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)] // Read from `Cargo.toml`
 #[command(next_line_help = true)]
 struct Cli {
+    #[arg(
+        long,
+        requires_all = ["first_lon", "second_lat", "second_lon"],
+        required_unless_present_any = ["area", "area_geojson", "center_lat", "corridor_gpx"]
+    )]
+    pub first_lat: Option<f32>,
+    #[arg(long, requires = "first_lat")]
+    pub first_lon: Option<f32>,
+    #[arg(long, requires = "first_lat")]
+    pub second_lat: Option<f32>,
+    #[arg(long, requires = "first_lat")]
+    pub second_lon: Option<f32>,
+
+    /// Named box as `NAME=FIRST_LAT,FIRST_LON,SECOND_LAT,SECOND_LON`. Can be repeated to check
+    /// several areas in the same run
+    #[arg(long, value_parser = parse_named_box)]
+    pub area: Vec<NamedArea>,
+
+    /// GeoJSON file with the polygons to filter by. Can be repeated
     #[arg(long)]
-    pub first_lat: f32,
+    pub area_geojson: Vec<PathBuf>,
+
+    /// Center of a circle to filter by, requires `--radius-m`
+    #[arg(long, requires_all = ["center_lon", "radius_m"])]
+    pub center_lat: Option<f32>,
+    #[arg(long, requires = "center_lat")]
+    pub center_lon: Option<f32>,
+    /// Radius in meters of the circle around the center
+    #[arg(long, requires = "center_lat", value_parser = parse_positive_meters)]
+    pub radius_m: Option<f64>,
+
+    /// Reference .gpx route that the tracks must follow, instead of filtering by area
+    #[arg(long, conflicts_with_all = ["first_lat", "center_lat", "area", "area_geojson"])]
+    pub corridor_gpx: Option<PathBuf>,
+    /// Maximum distance in meters of a point to the reference route
+    #[arg(long, default_value_t = 50.0, value_parser = parse_meters)]
+    pub corridor_buffer_m: f64,
+    /// Minimum percentage of the length of a track that must be inside the corridor
+    #[arg(long, default_value_t = 80.0)]
+    pub corridor_min_percentage: f32,
+
+    /// Name of an area that the tracks must touch. Can be repeated, all of them are required
     #[arg(long)]
-    pub first_lon: f32,
+    pub include: Vec<String>,
+    /// Name of an area that the tracks must never touch. Can be repeated
     #[arg(long)]
-    pub second_lat: f32,
+    pub exclude: Vec<String>,
+    /// Comma separated names of areas of which the tracks must touch at least one. Can be repeated
+    #[arg(long, value_delimiter = ',', num_args = 1)]
+    pub any_of: Vec<String>,
+
+    /// Comma separated names of areas that the tracks must visit in this order
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 1,
+        conflicts_with_all = ["include", "exclude", "any_of"]
+    )]
+    pub sequence: Vec<String>,
+
+    /// Also match the tracks whose line between two consecutive points crosses the area
     #[arg(long)]
-    pub second_lon: f32,
+    pub match_segments: bool,
+    /// Comma separated kinds of the points of the .gpx files that are checked against the areas:
+    /// `track`, `route` and `waypoint`. The other formats only have track points, which are always
+    /// checked
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 1,
+        value_parser = parse_point_kind,
+        default_value = "track"
+    )]
+    pub point_kinds: Vec<PointKind>,
+
+    /// Only the tracks that started at this time or later, e.g. `2023-03-01`,
+    /// `2023-03-01T08:00:00` or `2023-03-01T08:00:00+01:00`. Times without offset are in UTC
+    #[arg(long, value_parser = parse_date_time)]
+    pub after: Option<DateTime<Utc>>,
+    /// Only the tracks that started before this time, same format as `--after`
+    #[arg(long, value_parser = parse_date_time)]
+    pub before: Option<DateTime<Utc>>,
+
+    /// Comma separated activity types, e.g. `ride,run,walk` or the numeric Strava codes. Can be
+    /// repeated
+    #[arg(long, value_delimiter = ',', num_args = 1, value_parser = parse_activity_type)]
+    pub activity_type: Vec<ActivityType>,
+    /// Only the tracks whose name contains this text, ignoring the case
+    #[arg(long, conflicts_with = "name_regex")]
+    pub name: Option<String>,
+    /// Only the tracks whose name matches this regular expression
+    #[arg(long, value_parser = Regex::new)]
+    pub name_regex: Option<Regex>,
+
+    /// An area only counts as touched after spending this time inside, e.g. `90s`, `10m` or `1h`.
+    /// A number without unit is in seconds. Measured from the points inside the area, also with
+    /// `--match-segments`
+    #[arg(long, value_parser = parse_duration, conflicts_with_all = ["corridor_gpx", "sequence"])]
+    pub min_time_in_area: Option<Duration>,
+    /// An area only counts as touched after travelling this distance in meters inside, measured
+    /// from the points inside the area like `--min-time-in-area`
+    #[arg(long, value_parser = parse_meters, conflicts_with_all = ["corridor_gpx", "sequence"])]
+    pub min_distance_in_area: Option<f64>,
 
     #[arg(long, short = 'd', default_value_t = 300.0)]
     pub distance: f32,
 
-    #[arg(short = 'f', long)]
-    pub folder: PathBuf,
+    /// Folder with the .gpx files. Can be repeated
+    #[arg(short = 'f', long, required = true)]
+    pub folder: Vec<PathBuf>,
+    /// Also read the files in the subfolders
+    #[arg(short = 'r', long)]
+    pub recursive: bool,
+    /// Levels of subfolders to read, implies `--recursive`
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+    /// What to do with symbolic links: `ignore` them, only read links to `files` or `follow` the
+    /// links to folders too
+    #[arg(long, value_parser = parse_symlink_policy, default_value = "files")]
+    pub symlinks: SymlinkPolicy,
+    /// Only read the files whose path relative to the folder matches the glob, e.g. `2023/**`. Can
+    /// be repeated
+    #[arg(long, value_parser = Pattern::new)]
+    pub include_glob: Vec<Pattern>,
+    /// Skip the files and subfolders whose path relative to the folder matches the glob. Can be
+    /// repeated
+    #[arg(long, value_parser = Pattern::new)]
+    pub exclude_glob: Vec<Pattern>,
 
     #[arg(short = 't', long, default_value_t = 8)]
     pub threads: usize,
 
     #[arg(short = 'c', long)]
     pub copy_to: Option<PathBuf>,
+
+    /// Format of the matched tracks. The progress messages are always written to stderr
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output_format: OutputFormat,
+    /// File where the matched tracks are written instead of stdout
+    #[arg(short = 'o', long)]
+    pub output: Option<PathBuf>,
 }
 
 /// Parse the configuration that was provided in the command line and build a configuration
 pub fn get_cli_arguments() -> GpxGeoFilterConfig {
     let cli = Cli::parse();
 
-    let first_lat: f32 = cli.first_lat;
-    let first_lon: f32 = cli.first_lon;
-    let second_lat: f32 = cli.second_lat;
-    let second_lon: f32 = cli.second_lon;
-
-    let folder: PathBuf = cli.folder;
+    let max_depth = match (cli.max_depth, cli.recursive) {
+        (Some(max_depth), _) => max_depth,
+        (None, true) => usize::MAX,
+        (None, false) => 0,
+    };
+    let folders = FolderScan {
+        folders: cli.folder,
+        max_depth,
+        symlinks: cli.symlinks,
+        include: cli.include_glob,
+        exclude: cli.exclude_glob,
+    };
     let distance: f32 = cli.distance;
     let threads: usize = cli.threads;
     let copy_to: Option<PathBuf> = cli.copy_to;
+    let output_format: OutputFormat = cli.output_format;
+    let output: Option<PathBuf> = cli.output;
 
-    println!("> -----------------------------");
-    println!("> first_lat: {:?}", first_lat);
-    println!("> first_lon: {:?}", first_lon);
-    println!("> second_lat: {:?}", second_lat);
-    println!("> second_lon: {:?}", second_lon);
+    let mut areas: Vec<NamedArea> = Vec::new();
+    if let (Some(first_lat), Some(first_lon), Some(second_lat), Some(second_lon)) =
+        (cli.first_lat, cli.first_lon, cli.second_lat, cli.second_lon)
+    {
+        let area = SquaredFilter::new(
+            Coordinate::new(first_lat, first_lon),
+            Coordinate::new(second_lat, second_lon),
+        );
+        areas.push(NamedArea::new("area", area.into()));
+    }
+    if let (Some(center_lat), Some(center_lon), Some(radius_m)) =
+        (cli.center_lat, cli.center_lon, cli.radius_m)
+    {
+        let circle = CircleFilter::new(Coordinate::new(center_lat, center_lon), radius_m)
+            .unwrap_or_else(|err| exit_with(err));
+        areas.push(NamedArea::new("circle", circle.into()));
+    }
+    areas.extend(cli.area.iter().cloned());
+    let mut skipped_features: Vec<String> = Vec::new();
+    for area_geojson in &cli.area_geojson {
+        let (mut geojson_areas, mut skipped) =
+            load_geojson_areas(area_geojson).unwrap_or_else(|err| exit_with(err));
+        areas.append(&mut geojson_areas);
+        skipped_features.append(&mut skipped);
+    }
+    if let Some(name) = duplicate_area_name(&areas) {
+        Cli::command()
+            .error(
+                ErrorKind::InvalidValue,
+                format!("There is more than one area named '{name}'"),
+            )
+            .exit()
+    }
 
-    println!("> distance: {:?}", distance);
-    println!("> folder: {:?}", folder);
-    println!("> threads: {:?}", threads);
-    println!("> -----------------------------\n\n");
+    let corridor = cli.corridor_gpx.as_ref().map(|route| {
+        load_gpx_corridor(route, cli.corridor_buffer_m).unwrap_or_else(|err| exit_with(err))
+    });
+    let corridor_min_percentage: f32 = cli.corridor_min_percentage;
+    let match_mode = match cli.match_segments {
+        true => MatchMode::Segments,
+        false => MatchMode::Points,
+    };
+
+    let name = match (&cli.name, &cli.name_regex) {
+        (Some(name), _) => Some(
+            RegexBuilder::new(&regex::escape(name))
+                .case_insensitive(true)
+                .build()
+                .expect("An escaped text is always a valid regex"),
+        ),
+        (None, name_regex) => name_regex.clone(),
+    };
+    let conditions = TrackConditions {
+        after: cli.after,
+        before: cli.before,
+        activity_types: cli.activity_type.clone(),
+        name,
+        min_time_in_area: cli.min_time_in_area,
+        min_distance_in_area_m: cli.min_distance_in_area,
+        point_kinds: cli.point_kinds.clone(),
+    };
+
+    let expression = build_area_expression(&areas, &cli.include, &cli.exclude, &cli.any_of);
+    let sequence: Vec<NamedArea> = cli
+        .sequence
+        .iter()
+        .map(|name| find_area(&areas, name))
+        .collect();
+
+    eprintln!("> -----------------------------");
+    eprintln!("> first_lat: {:?}", cli.first_lat);
+    eprintln!("> first_lon: {:?}", cli.first_lon);
+    eprintln!("> second_lat: {:?}", cli.second_lat);
+    eprintln!("> second_lon: {:?}", cli.second_lon);
+    eprintln!("> center_lat: {:?}", cli.center_lat);
+    eprintln!("> center_lon: {:?}", cli.center_lon);
+    eprintln!("> radius_m: {:?}", cli.radius_m);
+    eprintln!("> area_geojson: {:?}", cli.area_geojson);
+    for area in &areas {
+        eprintln!("> area: {:?}", area.name);
+    }
+    if corridor.is_some() {
+        eprintln!("> corridor_gpx: {:?}", cli.corridor_gpx);
+        eprintln!("> corridor_buffer_m: {:?}", cli.corridor_buffer_m);
+        eprintln!("> corridor_min_percentage: {:?}", corridor_min_percentage);
+    }
+
+    if expression.is_some() {
+        eprintln!("> include: {:?}", cli.include);
+        eprintln!("> exclude: {:?}", cli.exclude);
+        eprintln!("> any_of: {:?}", cli.any_of);
+    }
+    if !sequence.is_empty() {
+        eprintln!("> sequence: {:?}", cli.sequence);
+    }
+    if conditions.has_minimum_in_area() {
+        eprintln!("> min_time_in_area: {:?}", cli.min_time_in_area);
+        eprintln!("> min_distance_in_area: {:?}", cli.min_distance_in_area);
+    }
+    if conditions.has_header_conditions() {
+        eprintln!("> after: {:?}", cli.after);
+        eprintln!("> before: {:?}", cli.before);
+        eprintln!("> activity_type: {:?}", cli.activity_type);
+        eprintln!("> name: {:?}", conditions.name.as_ref().map(Regex::as_str));
+    }
+    eprintln!("> match_mode: {:?}", match_mode);
+    eprintln!("> point_kinds: {:?}", conditions.point_kinds);
+    eprintln!("> distance: {:?}", distance);
+    eprintln!("> folders: {:?}", folders.folders);
+    eprintln!("> max_depth: {:?}", folders.max_depth);
+    eprintln!("> threads: {:?}", threads);
+    eprintln!("> -----------------------------\n\n");
 
     GpxGeoFilterConfig {
-        first_lat,
-        first_lon,
-        second_lat,
-        second_lon,
-        folder,
+        areas,
+        skipped_features,
+        expression,
+        sequence,
+        corridor,
+        corridor_min_percentage,
+        match_mode,
+        conditions,
+        folders,
         distance,
         threads,
         copy_to,
+        output_format,
+        output,
+    }
+}
+
+/// Combine the areas referenced by name in the include, exclude and any-of arguments. There is no
+/// expression when none of them is given and every track touching any area is kept
+fn build_area_expression(
+    areas: &[NamedArea],
+    include: &[String],
+    exclude: &[String],
+    any_of: &[String],
+) -> Option<AreaExpression> {
+    if include.is_empty() && exclude.is_empty() && any_of.is_empty() {
+        return None;
+    }
+
+    let find_area = |name: &String| -> NamedArea { find_area(areas, name) };
+
+    let mut expressions: Vec<AreaExpression> = include
+        .iter()
+        .map(|name| AreaExpression::Include(find_area(name)))
+        .collect();
+    if !any_of.is_empty() {
+        expressions.push(AreaExpression::AnyOf(
+            any_of
+                .iter()
+                .map(|name| AreaExpression::Include(find_area(name)))
+                .collect(),
+        ));
+    }
+    expressions.extend(
+        exclude
+            .iter()
+            .map(|name| AreaExpression::Exclude(find_area(name))),
+    );
+
+    Some(AreaExpression::AllOf(expressions))
+}
+
+/// First name used by more than one area. The areas are referenced and reported by their name, so
+/// it must be unique
+fn duplicate_area_name(areas: &[NamedArea]) -> Option<&str> {
+    let mut names = HashSet::new();
+    areas
+        .iter()
+        .find(|area| !names.insert(&area.name))
+        .map(|area| area.name.as_str())
+}
+
+/// Return the area with the given name, exiting with an error if there is none
+fn find_area(areas: &[NamedArea], name: &String) -> NamedArea {
+    match areas.iter().find(|area| &area.name == name) {
+        Some(area) => area.clone(),
+        None => Cli::command()
+            .error(
+                ErrorKind::InvalidValue,
+                format!("There is no area named '{name}'"),
+            )
+            .exit(),
+    }
+}
+
+/// Parse the policy for symbolic links, `ignore`, `files` or `follow`
+fn parse_symlink_policy(value: &str) -> Result<SymlinkPolicy, String> {
+    match value.to_lowercase().as_str() {
+        "ignore" => Ok(SymlinkPolicy::Ignore),
+        "files" => Ok(SymlinkPolicy::Files),
+        "follow" => Ok(SymlinkPolicy::Follow),
+        _ => Err(format!(
+            "'{value}' is not a symlink policy, use 'ignore', 'files' or 'follow'"
+        )),
+    }
+}
+
+/// Parse a kind of point, by its name or the one of its .gpx element
+fn parse_point_kind(value: &str) -> Result<PointKind, String> {
+    match value.trim().to_lowercase().as_str() {
+        "track" | "trkpt" => Ok(PointKind::Track),
+        "route" | "rtept" => Ok(PointKind::Route),
+        "waypoint" | "wpt" => Ok(PointKind::Waypoint),
+        _ => Err(format!(
+            "'{value}' is not a kind of point, use 'track', 'route' or 'waypoint'"
+        )),
+    }
+}
+
+/// Exit with the error of a file or a value given in the arguments
+fn exit_with(error: Error) -> ! {
+    let kind = match error {
+        Error::InvalidArgument { .. } => ErrorKind::ValueValidation,
+        _ => ErrorKind::Io,
+    };
+    Cli::command().error(kind, error).exit()
+}
+
+/// Parse a box given as `NAME=FIRST_LAT,FIRST_LON,SECOND_LAT,SECOND_LON`
+fn parse_named_box(value: &str) -> Result<NamedArea, String> {
+    let (name, corners) = value
+        .split_once('=')
+        .ok_or("Expected NAME=FIRST_LAT,FIRST_LON,SECOND_LAT,SECOND_LON")?;
+
+    let corners = corners
+        .split(',')
+        .map(|number| number.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|err| format!("Invalid coordinate in '{corners}': {err}"))?;
+    let [first_lat, first_lon, second_lat, second_lon] = corners[..] else {
+        return Err(format!("Expected 4 coordinates, found {}", corners.len()));
+    };
+
+    let area = SquaredFilter::new(
+        Coordinate::new(first_lat, first_lon),
+        Coordinate::new(second_lat, second_lon),
+    );
+    Ok(NamedArea::new(name.trim(), area.into()))
+}
+
+/// Parse a distance in meters, 0 or more
+fn parse_meters(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(meters) if meters.is_finite() && meters >= 0.0 => Ok(meters),
+        _ => Err(format!("Expected a distance in meters, found '{value}'")),
+    }
+}
+
+/// Parse a distance in meters greater than 0
+fn parse_positive_meters(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(meters) if meters.is_finite() && meters > 0.0 => Ok(meters),
+        _ => Err(format!(
+            "Expected a distance in meters greater than 0, found '{value}'"
+        )),
+    }
+}
+
+fn parse_activity_type(value: &str) -> Result<ActivityType, String> {
+    Ok(ActivityType::parse(value))
+}
+
+/// Parse a duration given as a number followed by `s`, `m` or `h`, or just a number of seconds
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, seconds_per_unit) = match value.char_indices().last() {
+        Some((index, 's')) => (&value[..index], 1.0),
+        Some((index, 'm')) => (&value[..index], 60.0),
+        Some((index, 'h')) => (&value[..index], 3600.0),
+        _ => (value, 1.0),
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("Expected a duration like 90s, 10m or 1h, found '{value}'"))?;
+
+    Ok(Duration::milliseconds(
+        (number * seconds_per_unit * 1000.0) as i64,
+    ))
+}
+
+/// Parse an ISO 8601 date or date and time. When there is no offset the time is taken as UTC
+fn parse_date_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
     }
+    if let Ok(time) = value.parse::<NaiveDateTime>() {
+        return Ok(time.and_utc());
+    }
+    if let Ok(date) = value.parse::<NaiveDate>() {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+    Err(format!(
+        "Expected a date like 2023-03-01 or a time like 2023-03-01T08:00:00+01:00, found '{value}'"
+    ))
+}
+
+#[test]
+fn parse_date_time_argument() {
+    let expected = "2023-03-01T07:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+    assert_eq!(Ok(expected), parse_date_time("2023-03-01T08:00:00+01:00"));
+    assert_eq!(Ok(expected), parse_date_time("2023-03-01T07:00:00"));
+    assert_eq!(
+        Ok(expected - chrono::Duration::hours(7)),
+        parse_date_time("2023-03-01")
+    );
+    assert!(parse_date_time("01/03/2023").is_err());
+}
+
+#[test]
+fn parse_duration_argument() {
+    assert_eq!(Ok(Duration::seconds(90)), parse_duration("90s"));
+    assert_eq!(Ok(Duration::seconds(90)), parse_duration("90"));
+    assert_eq!(Ok(Duration::seconds(90)), parse_duration("1.5m"));
+    assert_eq!(Ok(Duration::hours(2)), parse_duration("2h"));
+    assert!(parse_duration("2 days").is_err());
+}
+
+#[test]
+fn parse_distance_argument() {
+    assert_eq!(Ok(50.0), parse_positive_meters("50"));
+    assert_eq!(Ok(0.0), parse_meters("0"));
+    assert!(parse_meters("-10").is_err());
+    for value in ["0", "-10", "NaN", "inf", "far"] {
+        assert!(parse_positive_meters(value).is_err());
+    }
+}
+
+#[test]
+fn parse_named_box_argument() {
+    let area = parse_named_box("Old town=49.44,11.06,49.46,11.09").unwrap();
+
+    assert_eq!("Old town", area.name);
+    assert_eq!(
+        area.area.bounding_box().unwrap().top_right,
+        Coordinate::new(49.46, 11.09)
+    );
+    assert!(parse_named_box("49.44,11.06,49.46,11.09").is_err());
+    assert!(parse_named_box("A=49.44,11.06,49.46").is_err());
+    assert!(parse_named_box("A=49.44,11.06,49.46,east").is_err());
+}
+
+#[test]
+fn find_duplicate_area_names() {
+    let areas = [
+        parse_named_box("Old town=49.44,11.06,49.46,11.09").unwrap(),
+        parse_named_box("Park=49.40,11.00,49.42,11.02").unwrap(),
+    ];
+    assert_eq!(None, duplicate_area_name(&areas));
+
+    let duplicate = parse_named_box("Park=49.50,11.00,49.52,11.02").unwrap();
+    let areas = [areas[0].clone(), areas[1].clone(), duplicate];
+    assert_eq!(Some("Park"), duplicate_area_name(&areas));
 }
 
 #[test]
@@ -1,12 +1,24 @@
 use std::path::PathBuf;
 
+use crate::{
+    model::{AreaExpression, CorridorFilter, FolderScan, MatchMode, NamedArea, TrackConditions},
+    output::OutputFormat,
+};
+
 pub struct GpxGeoFilterConfig {
-    pub first_lat: f32,
-    pub first_lon: f32,
-    pub second_lat: f32,
-    pub second_lon: f32,
+    pub areas: Vec<NamedArea>,
+    /// Names of the features of the GeoJSON files that are not polygons
+    pub skipped_features: Vec<String>,
+    pub expression: Option<AreaExpression>,
+    pub sequence: Vec<NamedArea>,
+    pub corridor: Option<CorridorFilter>,
+    pub corridor_min_percentage: f32,
+    pub match_mode: MatchMode,
+    pub conditions: TrackConditions,
     pub distance: f32,
-    pub folder: PathBuf,
+    pub folders: FolderScan,
     pub threads: usize,
     pub copy_to: Option<PathBuf>,
+    pub output_format: OutputFormat,
+    pub output: Option<PathBuf>,
 }
@@ -0,0 +1,116 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use quick_xml::reader::Reader;
+
+/// Everything that can go wrong while reading, filtering or copying the tracks
+#[derive(Debug)]
+pub enum Error {
+    /// A file or folder could not be opened, read or written
+    Io { path: PathBuf, source: io::Error },
+    /// A file is not valid XML
+    Xml {
+        path: PathBuf,
+        position: usize,
+        message: String,
+    },
+    /// A number in a file could not be parsed, or a file has a value that can not be used
+    InvalidValue { path: PathBuf, value: String },
+    /// An argument given to filter the files can not be used
+    InvalidArgument { name: String, message: String },
+    /// A file is not valid GeoJSON
+    GeoJson { path: PathBuf, message: String },
+    /// A file is not a valid FIT file
+    Fit { path: PathBuf, message: String },
+    /// A .zip archive or one of its entries can not be read
+    Archive { path: PathBuf, message: String },
+    /// The pool of threads could not be created
+    ThreadPool(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Error {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// File in which the error happened, if it was caused by one
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            Error::Io { path, .. }
+            | Error::Xml { path, .. }
+            | Error::InvalidValue { path, .. }
+            | Error::GeoJson { path, .. }
+            | Error::Fit { path, .. }
+            | Error::Archive { path, .. } => Some(path),
+            Error::InvalidArgument { .. } | Error::ThreadPool(_) => None,
+        }
+    }
+}
+
+/// Error of the XML reader at its current position in the file
+pub(crate) fn xml_error<R>(path: &Path, reader: &Reader<R>, error: quick_xml::Error) -> Error {
+    Error::Xml {
+        path: path.to_path_buf(),
+        position: reader.buffer_position(),
+        message: error.to_string(),
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Xml {
+                path,
+                position,
+                message,
+            } => write!(
+                f,
+                "{}: invalid XML at position {}: {}",
+                path.display(),
+                position,
+                message
+            ),
+            Error::InvalidValue { path, value } => {
+                write!(f, "{}: invalid value '{}'", path.display(), value)
+            }
+            Error::InvalidArgument { name, message } => {
+                write!(f, "invalid argument '{name}': {message}")
+            }
+            Error::GeoJson { path, message } => {
+                write!(f, "{}: invalid GeoJSON: {}", path.display(), message)
+            }
+            Error::Fit { path, message } => {
+                write!(f, "{}: invalid FIT file: {}", path.display(), message)
+            }
+            Error::Archive { path, message } => {
+                write!(f, "{}: invalid archive: {}", path.display(), message)
+            }
+            Error::ThreadPool(message) => {
+                write!(f, "the thread pool could not be created: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<rayon::ThreadPoolBuildError> for Error {
+    fn from(error: rayon::ThreadPoolBuildError) -> Self {
+        Error::ThreadPool(error.to_string())
+    }
+}
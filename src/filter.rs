@@ -1,159 +1,654 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
+    io::BufRead,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
+use geojson::JsonObject;
 use rayon::prelude::*;
 use rayon::ThreadPoolBuilder;
 use regex::Regex;
 
 use crate::{
-    io::read_xml_file,
-    model::{Coordinate, SquaredFilter},
-    parser::{compile_coordinate_regex, extract_first_coordinate_from_text},
-    utils::{is_point_in_area, is_point_more_than_x_distance_from_filter},
+    error::{Error, Result},
+    fit::read_first_fit_coordinate,
+    format::TrackFormat,
+    geojson_track::read_geojson_track,
+    io::{open_track, read_track, read_track_header},
+    kml::read_first_kml_coordinate,
+    model::{
+        AreaExpression, AreaFilter, Coordinate, FilterResult, MatchMode, MatchReason, NamedArea,
+        PointKind, TrackConditions, TrackPoint, TrackStatistics,
+    },
+    parser::{compile_coordinate_regex, extract_coordinates_from_text},
+    statistics::compute_statistics,
+    tcx::read_first_tcx_coordinate,
+    utils::{
+        is_point_in_area, is_point_more_than_x_distance_from_filter, is_segment_in_area,
+        percentage_of_track_in_area, visit_in_area,
+    },
 };
 
-type SafeSharedVec = Arc<Mutex<Vec<PathBuf>>>;
+type SafeSharedVec<T> = Arc<Mutex<Vec<T>>>;
+/// First point of each kind found in a file, with the properties of a .geojson track
+type FirstPoints = (Vec<(Coordinate, PointKind)>, Option<JsonObject>);
 
-/// Filters all the tracks that at a distance longer than the provided distance from the provided
-/// area. Filtering is based on the first point found in the file using a regex on latitude and
-/// longitude
+/// Filters all the tracks that at a distance longer than the provided distance from all the
+/// provided areas. Filtering is based on the first point of each checked kind found in the file
+/// using a regex on latitude and longitude, a file is kept when any of them is close. The tracks
+/// with one of those points already in an area are returned separately as results with the name
+/// of the first area containing it, without statistics. The tracks far from all the areas are
+/// returned as results without any area, as they can still match excluded areas. Only the points
+/// of the kinds checked by the conditions are used in .gpx files, the other formats only have
+/// track points which are always checked. The files where no point can be found this way are kept
+/// for the full scan
 ///
-/// # Panics
-///
-/// Panics if:
-/// 1. There is a problem with the threads.
-/// 1. The directory does not exist.
+/// The errors of each file are added to `errors` and the file is skipped. Fails only if the thread
+/// pool can not be created.
 pub fn prefilter_files(
     paths: Vec<PathBuf>,
-    area: &SquaredFilter,
+    areas: &[NamedArea],
     distance: f32,
+    conditions: &TrackConditions,
     threads: usize,
-) -> (Vec<PathBuf>, Vec<PathBuf>) {
-    let thread_pool = build_thread_pool(threads);
+    errors: &Mutex<Vec<Error>>,
+) -> Result<(Vec<FilterResult>, Vec<PathBuf>, Vec<FilterResult>)> {
+    let thread_pool = build_thread_pool(threads)?;
 
     let nearby_paths = SafeSharedVec::default();
     let area_paths = SafeSharedVec::default();
+    let far_paths = SafeSharedVec::default();
 
-    let re = compile_coordinate_regex();
+    let kinds = conditions.checked_kinds();
+    let re = compile_coordinate_regex(kinds);
 
     thread_pool.install(|| {
         paths.into_par_iter().for_each(|path| {
             let nearby_paths_clone = Arc::clone(&nearby_paths);
             let area_paths_clone = Arc::clone(&area_paths);
+            let far_paths_clone = Arc::clone(&far_paths);
             let re_clone = re.clone();
+            let now = Instant::now();
+
+            let Some((first_points, properties)) = report(
+                extract_first_coordinates_from_file(&path, re_clone, kinds),
+                errors,
+            ) else {
+                return;
+            };
 
-            let coordinate = extract_first_coordinate_from_file(&path, re_clone);
-            match coordinate {
-                Some(coordinate) if is_point_in_area(area, &coordinate) => {
-                    let mut area_paths = area_paths_clone.lock().unwrap();
-                    area_paths.push(path);
-                }
-                Some(coordinate)
-                    if !is_point_more_than_x_distance_from_filter(area, &coordinate, distance) =>
-                {
-                    let mut nearby_paths = nearby_paths_clone.lock().unwrap();
-                    nearby_paths.push(path);
-                }
-                _ => (),
+            // e.g. a .gpx file whose points are written over several lines
+            if first_points.is_empty() {
+                nearby_paths_clone.lock().unwrap().push(path);
+                return;
+            }
+
+            let in_area = first_points.iter().find_map(|(coordinate, kind)| {
+                find_area_containing_point(areas, coordinate).map(|area| (coordinate, kind, area))
+            });
+            if let Some((coordinate, kind, area)) = in_area {
+                let mut area_paths = area_paths_clone.lock().unwrap();
+                area_paths.push(FilterResult {
+                    path,
+                    reason: MatchReason::Prefilter,
+                    first_matched_point: Some(*coordinate),
+                    matched_kind: Some(*kind),
+                    areas: vec![area.name.clone()],
+                    statistics: None,
+                    properties,
+                    entries: Vec::new(),
+                    elapsed: now.elapsed(),
+                });
+            } else if first_points.iter().any(|(coordinate, _)| {
+                areas.iter().any(|area| {
+                    !is_point_more_than_x_distance_from_filter(&area.area, coordinate, distance)
+                })
+            }) {
+                let mut nearby_paths = nearby_paths_clone.lock().unwrap();
+                nearby_paths.push(path);
+            } else {
+                let mut far_paths = far_paths_clone.lock().unwrap();
+                far_paths.push(FilterResult {
+                    path,
+                    reason: MatchReason::Prefilter,
+                    first_matched_point: None,
+                    matched_kind: None,
+                    areas: Vec::new(),
+                    statistics: None,
+                    properties,
+                    entries: Vec::new(),
+                    elapsed: now.elapsed(),
+                });
             }
         });
     });
 
     let area = Arc::try_unwrap(area_paths).unwrap().into_inner().unwrap();
     let nearby = Arc::try_unwrap(nearby_paths).unwrap().into_inner().unwrap();
-    (area, nearby)
+    let far = Arc::try_unwrap(far_paths).unwrap().into_inner().unwrap();
+    Ok((area, nearby, far))
 }
 
-/// .
-/// Filters all the tracks that do not have at least one point in the provided area by looping
-/// through all the points
+/// Filters all the tracks that do not meet the conditions on their start time, name and type. Only
+/// the start of the file is read, so this is cheap enough to run before any coordinate is checked
 ///
-/// # Panics
+/// The errors of each file are added to `errors` and the file is skipped. Fails only if the thread
+/// pool can not be created.
+pub fn filter_tracks_not_meeting_conditions(
+    paths: Vec<PathBuf>,
+    conditions: &TrackConditions,
+    threads: usize,
+    errors: &Mutex<Vec<Error>>,
+) -> Result<Vec<PathBuf>> {
+    let thread_pool = build_thread_pool(threads)?;
+
+    let filtered_paths = SafeSharedVec::default();
+    thread_pool.install(|| {
+        paths.into_par_iter().for_each(|path| {
+            let filtered_paths_clone = Arc::clone(&filtered_paths);
+
+            let Some(header) = report(read_track_header(&path), errors) else {
+                return;
+            };
+            if conditions.is_met_by(&header) {
+                let mut filtered_paths = filtered_paths_clone.lock().unwrap();
+                filtered_paths.push(path);
+            }
+        });
+    });
+
+    Ok(Arc::try_unwrap(filtered_paths)
+        .unwrap()
+        .into_inner()
+        .unwrap())
+}
+
+/// Filters all the tracks that do not have at least one point in any of the provided areas by
+/// looping through all the points, or all the segments between them depending on the `mode`. The
+/// file is parsed only once and the names of all the areas reached by the track are returned. With
+/// a minimum time or distance in the conditions, an area is only reached after spending them inside
 ///
-/// Panics if:
-/// 1. There is a problem with the threads.
-/// 1. The directory does not exist.
+/// The errors of each file are added to `errors` and the file is skipped. Fails only if the thread
+/// pool can not be created.
 pub fn filter_tracks_outside_area(
     paths: Vec<PathBuf>,
-    area: &SquaredFilter,
+    areas: &[NamedArea],
+    mode: MatchMode,
+    conditions: &TrackConditions,
     threads: usize,
-) -> Vec<PathBuf> {
-    let thread_pool = build_thread_pool(threads);
+    errors: &Mutex<Vec<Error>>,
+) -> Result<Vec<FilterResult>> {
+    let thread_pool = build_thread_pool(threads)?;
+    let areas: Vec<&NamedArea> = areas.iter().collect();
 
     let filtered_paths = SafeSharedVec::default();
     thread_pool.install(|| {
         paths.into_par_iter().for_each(|path| {
             let filtered_paths_clone = Arc::clone(&filtered_paths);
-            let area_clone = area.clone();
+            let now = Instant::now();
 
-            if file_contains_point_in_area(&path, &area_clone) {
+            let Some(scan) = report(scan_file(&path, &areas, mode, conditions), errors) else {
+                return;
+            };
+            if scan.touched.contains(&true) {
                 let mut filtered_paths = filtered_paths_clone.lock().unwrap();
-                filtered_paths.push(path.clone());
+                filtered_paths.push(scan.into_result(path, &areas, now));
             }
         });
     });
 
-    Arc::try_unwrap(filtered_paths)
+    Ok(Arc::try_unwrap(filtered_paths)
         .unwrap()
         .into_inner()
+        .unwrap())
+}
+
+/// Compute the statistics of the results that matched without a full scan, like the tracks that
+/// start inside an area. Their files are parsed again, so this is only done when the statistics
+/// are shown
+///
+/// The errors of each file are added to `errors` and the file is skipped. Fails only if the thread
+/// pool can not be created.
+pub fn compute_statistics_of_tracks(
+    results: Vec<FilterResult>,
+    conditions: &TrackConditions,
+    threads: usize,
+    errors: &Mutex<Vec<Error>>,
+) -> Result<Vec<FilterResult>> {
+    let thread_pool = build_thread_pool(threads)?;
+
+    let matched_tracks = SafeSharedVec::default();
+    thread_pool.install(|| {
+        results.into_par_iter().for_each(|mut result| {
+            let matched_tracks_clone = Arc::clone(&matched_tracks);
+            let now = Instant::now();
+
+            let Some((points, _)) = report(read_checked_points(&result.path, conditions), errors)
+            else {
+                return;
+            };
+            result.statistics = Some(compute_statistics(&points));
+            result.elapsed += now.elapsed();
+            let mut matched_tracks = matched_tracks_clone.lock().unwrap();
+            matched_tracks.push(result);
+        });
+    });
+
+    Ok(Arc::try_unwrap(matched_tracks)
         .unwrap()
+        .into_inner()
+        .unwrap())
 }
 
-/// Return a thread pool with the given number of threads
-fn build_thread_pool(threads: usize) -> rayon::ThreadPool {
-    let thread_pool = ThreadPoolBuilder::new()
-        .num_threads(threads)
-        .build()
-        .expect("The thread pool could not be created");
-    thread_pool
-}
-
-/// Use a regex to find the first point the file
-fn extract_first_coordinate_from_file(path: &PathBuf, re: Regex) -> Option<Coordinate> {
-    let file = File::open(path).unwrap();
-    let reader = BufReader::new(file);
-
-    for line in reader.lines() {
-        
-        let line = match line {
-            Ok(line) => line,
-            Err(_) => continue, // Skip lines with errors
-        };
-
-        let coordinate = extract_first_coordinate_from_text(&re, line.as_str());
-        match coordinate {
-            Some(coordinate) => return Some(coordinate),
-            None => continue,
+/// Filters all the tracks that do not have at least the given percentage of their length inside
+/// the area by looping through all the points
+///
+/// The errors of each file are added to `errors` and the file is skipped. Fails only if the thread
+/// pool can not be created.
+pub fn filter_tracks_mostly_outside_area(
+    paths: Vec<PathBuf>,
+    area: &NamedArea,
+    min_percentage: f32,
+    conditions: &TrackConditions,
+    threads: usize,
+    errors: &Mutex<Vec<Error>>,
+) -> Result<Vec<FilterResult>> {
+    let thread_pool = build_thread_pool(threads)?;
+
+    let filtered_paths = SafeSharedVec::default();
+    thread_pool.install(|| {
+        paths.into_par_iter().for_each(|path| {
+            let filtered_paths_clone = Arc::clone(&filtered_paths);
+            let now = Instant::now();
+
+            let Some((points, properties)) = report(read_checked_points(&path, conditions), errors)
+            else {
+                return;
+            };
+            if percentage_of_track_in_area(&area.area, &points) >= min_percentage {
+                let first_matched_point = points
+                    .iter()
+                    .find(|point| is_point_in_area(&area.area, &point.coordinate));
+                let statistics = compute_statistics(&points);
+                let mut filtered_paths = filtered_paths_clone.lock().unwrap();
+                filtered_paths.push(FilterResult {
+                    path,
+                    reason: MatchReason::FullScan,
+                    first_matched_point: first_matched_point.map(|point| point.coordinate),
+                    matched_kind: first_matched_point.map(|point| point.kind),
+                    areas: vec![area.name.clone()],
+                    statistics: Some(statistics),
+                    properties,
+                    entries: Vec::new(),
+                    elapsed: now.elapsed(),
+                });
+            }
+        });
+    });
+
+    Ok(Arc::try_unwrap(filtered_paths)
+        .unwrap()
+        .into_inner()
+        .unwrap())
+}
+
+/// Filters all the tracks for which the expression is not true, evaluating all its areas in a
+/// single pass over the points of each file. With a minimum time or distance in the conditions,
+/// an area is only touched after spending them inside
+///
+/// The errors of each file are added to `errors` and the file is skipped. Fails only if the thread
+/// pool can not be created.
+pub fn filter_tracks_by_expression(
+    paths: Vec<PathBuf>,
+    expression: &AreaExpression,
+    mode: MatchMode,
+    conditions: &TrackConditions,
+    threads: usize,
+    errors: &Mutex<Vec<Error>>,
+) -> Result<Vec<FilterResult>> {
+    let thread_pool = build_thread_pool(threads)?;
+    let areas = expression.areas();
+
+    let filtered_paths = SafeSharedVec::default();
+    thread_pool.install(|| {
+        paths.into_par_iter().for_each(|path| {
+            let filtered_paths_clone = Arc::clone(&filtered_paths);
+            let now = Instant::now();
+
+            let Some(scan) = report(scan_file(&path, &areas, mode, conditions), errors) else {
+                return;
+            };
+            if expression.evaluate(&scan.touched) {
+                let mut filtered_paths = filtered_paths_clone.lock().unwrap();
+                filtered_paths.push(scan.into_result(path, &areas, now));
+            }
+        });
+    });
+
+    Ok(Arc::try_unwrap(filtered_paths)
+        .unwrap()
+        .into_inner()
+        .unwrap())
+}
+
+/// Filters all the tracks that do not visit the areas in the given order. The point at which the
+/// track entered each area, with its time, is returned in the `entries` of the result
+///
+/// The errors of each file are added to `errors` and the file is skipped. Fails only if the thread
+/// pool can not be created.
+pub fn filter_tracks_not_following_sequence(
+    paths: Vec<PathBuf>,
+    areas: &[NamedArea],
+    mode: MatchMode,
+    conditions: &TrackConditions,
+    threads: usize,
+    errors: &Mutex<Vec<Error>>,
+) -> Result<Vec<FilterResult>> {
+    let thread_pool = build_thread_pool(threads)?;
+
+    let filtered_paths = SafeSharedVec::default();
+    thread_pool.install(|| {
+        paths.into_par_iter().for_each(|path| {
+            let filtered_paths_clone = Arc::clone(&filtered_paths);
+            let now = Instant::now();
+
+            let Some((points, properties)) = report(read_checked_points(&path, conditions), errors)
+            else {
+                return;
+            };
+            if let Some(entries) = find_area_sequence(&points, areas, mode) {
+                let mut filtered_paths = filtered_paths_clone.lock().unwrap();
+                filtered_paths.push(FilterResult {
+                    path,
+                    reason: MatchReason::FullScan,
+                    first_matched_point: Some(entries[0].coordinate),
+                    matched_kind: Some(entries[0].kind),
+                    areas: areas.iter().map(|area| area.name.clone()).collect(),
+                    statistics: Some(compute_statistics(&points)),
+                    properties,
+                    entries,
+                    elapsed: now.elapsed(),
+                });
+            }
+        });
+    });
+
+    Ok(Arc::try_unwrap(filtered_paths)
+        .unwrap()
+        .into_inner()
+        .unwrap())
+}
+
+/// Return the first point inside each of the areas, visited one after the other. The same point
+/// may enter several consecutive areas if they overlap. With `MatchMode::Segments` an area is also
+/// entered when the line to the next point of the same line crosses it, at the first point of
+/// that segment
+fn find_area_sequence(
+    points: &[TrackPoint],
+    areas: &[NamedArea],
+    mode: MatchMode,
+) -> Option<Vec<TrackPoint>> {
+    let mut entries: Vec<TrackPoint> = Vec::new();
+
+    for (index, point) in points.iter().enumerate() {
+        let next = match mode {
+            MatchMode::Segments => points.get(index + 1).filter(|next| next.line == point.line),
+            MatchMode::Points => None,
+        };
+        let enters = |area: &AreaFilter| match next {
+            Some(next) => is_segment_in_area(area, &point.coordinate, &next.coordinate),
+            None => is_point_in_area(area, &point.coordinate),
+        };
+        while entries.len() < areas.len() && enters(&areas[entries.len()].area) {
+            entries.push(*point);
+        }
+        if entries.len() == areas.len() {
+            return Some(entries);
         }
     }
+
     None
 }
 
-/// Check the files that are further away the given distance from the target area
-fn file_contains_point_in_area(path: &PathBuf, area: &SquaredFilter) -> bool {
-    let coordinates = read_xml_file(&path);
+/// Read the points of the file of the kinds checked by the conditions, together with the
+/// properties of a .geojson track. The formats other than .gpx only have track points, which are
+/// always checked
+fn read_checked_points(
+    path: &Path,
+    conditions: &TrackConditions,
+) -> Result<(Vec<TrackPoint>, Option<JsonObject>)> {
+    let (mut points, properties) = read_track(path)?;
+    if TrackFormat::of(path) == Some(TrackFormat::Gpx) {
+        points.retain(|point| conditions.checks_kind(point.kind));
+    }
+    Ok((points, properties))
+}
 
-    for coordinate in coordinates {
-        if is_point_in_area(area, &coordinate) {
-            return true;
+/// Return a thread pool with the given number of threads
+fn build_thread_pool(threads: usize) -> Result<rayon::ThreadPool> {
+    Ok(ThreadPoolBuilder::new().num_threads(threads).build()?)
+}
+
+/// Keep the error of a file to report it at the end of the run instead of aborting it
+fn report<T>(result: Result<T>, errors: &Mutex<Vec<Error>>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(err) => {
+            errors.lock().unwrap().push(err);
+            None
+        }
+    }
+}
+
+/// Use a regex to find the first point of each of the given kinds in the file. The waypoints,
+/// routes and tracks of a .gpx file come in this order, so it is read until its first track point
+/// or until there is a point of every kind. The .fit, .tcx and .kml files are read until their
+/// first point with a position, which in .tcx and .kml files is spread over several lines. The
+/// .geojson files can only be parsed as a whole, so their properties are returned as well
+fn extract_first_coordinates_from_file(
+    path: &Path,
+    re: Regex,
+    kinds: &[PointKind],
+) -> Result<FirstPoints> {
+    let reader = open_track(path)?;
+    // The points of the formats other than .gpx are all track points
+    let track_point = |coordinate: Option<Coordinate>| {
+        let points = coordinate.map(|coordinate| (coordinate, PointKind::Track));
+        Ok((points.into_iter().collect(), None))
+    };
+    match TrackFormat::of(path) {
+        Some(TrackFormat::Fit) => return track_point(read_first_fit_coordinate(reader, path)?),
+        Some(TrackFormat::Tcx) => return track_point(read_first_tcx_coordinate(reader, path)?),
+        Some(TrackFormat::Kml | TrackFormat::Kmz) => {
+            return track_point(read_first_kml_coordinate(reader, path)?)
+        }
+        Some(TrackFormat::GeoJson) => {
+            let track = read_geojson_track(reader, path)?;
+            let first_point = track
+                .points
+                .first()
+                .map(|point| (point.coordinate, point.kind));
+            return Ok((first_point.into_iter().collect(), track.properties));
         }
+        _ => (),
     }
 
-    false
+    let mut first_points: Vec<(Coordinate, PointKind)> = Vec::new();
+    // Lines that are not valid UTF-8 are still searched, but a read error, e.g. of a corrupt
+    // compressed file, would be returned forever
+    for line in reader.split(b'\n') {
+        let line = line.map_err(|err| Error::io(path, err))?;
+        let line = String::from_utf8_lossy(&line);
+
+        for point in extract_coordinates_from_text(&re, &line, path) {
+            let (coordinate, kind) = point?;
+            if !first_points.iter().any(|(_, found)| *found == kind) {
+                first_points.push((coordinate, kind));
+            }
+            if kind == PointKind::Track || first_points.len() == kinds.len() {
+                return Ok((first_points, None));
+            }
+        }
+    }
+    Ok((first_points, None))
+}
+
+/// Return the first of the areas that contains the point
+fn find_area_containing_point<'a>(
+    areas: &'a [NamedArea],
+    point: &Coordinate,
+) -> Option<&'a NamedArea> {
+    areas
+        .iter()
+        .find(|area| is_point_in_area(&area.area, point))
+}
+
+/// Result of checking all the points of a file against the areas
+struct FileScan {
+    /// Whether each of the areas was touched, in the same order as they were provided
+    touched: Vec<bool>,
+    first_matched_point: Option<TrackPoint>,
+    statistics: TrackStatistics,
+    properties: Option<JsonObject>,
+}
+
+impl FileScan {
+    fn into_result(self, path: PathBuf, areas: &[&NamedArea], start: Instant) -> FilterResult {
+        let areas = areas
+            .iter()
+            .zip(&self.touched)
+            .filter(|(_, touched)| **touched)
+            .map(|(area, _)| area.name.clone())
+            .collect();
+
+        FilterResult {
+            path,
+            reason: MatchReason::FullScan,
+            first_matched_point: self.first_matched_point.map(|point| point.coordinate),
+            matched_kind: self.first_matched_point.map(|point| point.kind),
+            areas,
+            statistics: Some(self.statistics),
+            properties: self.properties,
+            entries: Vec::new(),
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+/// Check which of the areas are touched by the points of the checked kinds of the file and compute
+/// their statistics. When there is a minimum time or distance inside the areas the whole track is
+/// checked for each of them. The visit is measured between the points inside the area whatever the
+/// `mode`, so a line that crosses an area without any point inside adds nothing to it
+fn scan_file(
+    path: &Path,
+    areas: &[&NamedArea],
+    mode: MatchMode,
+    conditions: &TrackConditions,
+) -> Result<FileScan> {
+    let (points, properties) = read_checked_points(path, conditions)?;
+    let statistics = compute_statistics(&points);
+
+    if !conditions.has_minimum_in_area() {
+        let (touched, first_matched_point) = find_touched_areas(&points, areas, mode);
+        return Ok(FileScan {
+            touched,
+            first_matched_point: first_matched_point.map(|index| points[index]),
+            statistics,
+            properties,
+        });
+    }
+
+    let touched: Vec<bool> = areas
+        .iter()
+        .map(|area| conditions.is_visit_long_enough(&visit_in_area(&area.area, &points)))
+        .collect();
+    let first_matched_point = points.into_iter().find(|point| {
+        areas
+            .iter()
+            .zip(&touched)
+            .any(|(area, touched)| *touched && is_point_in_area(&area.area, &point.coordinate))
+    });
+    Ok(FileScan {
+        touched,
+        first_matched_point,
+        statistics,
+        properties,
+    })
+}
+
+/// Check which of the areas are touched by the points in a single pass over them, and the index of
+/// the first point at which any of them was touched. For segments, that is the first point of the
+/// segment. Only consecutive points of the same line, a track segment or a route, make a segment,
+/// the waypoints are always checked on their own. Areas that were already touched are not checked
+/// again and the loop stops once all of them are
+fn find_touched_areas(
+    points: &[TrackPoint],
+    areas: &[&NamedArea],
+    mode: MatchMode,
+) -> (Vec<bool>, Option<usize>) {
+    let mut touched = vec![false; areas.len()];
+    let mut pending = areas.len();
+    let mut first_matched_point: Option<usize> = None;
+
+    let mut check = |index: usize, is_in_area: &dyn Fn(&AreaFilter) -> bool| {
+        for (area_index, area) in areas.iter().enumerate() {
+            if !touched[area_index] && is_in_area(&area.area) {
+                touched[area_index] = true;
+                pending -= 1;
+                first_matched_point.get_or_insert(index);
+            }
+        }
+        pending == 0
+    };
+
+    for (index, point) in points.iter().enumerate() {
+        let next = match mode {
+            MatchMode::Segments => points.get(index + 1).filter(|next| next.line == point.line),
+            MatchMode::Points => None,
+        };
+        let all_touched = match next {
+            Some(next) => check(index, &|area| {
+                is_segment_in_area(area, &point.coordinate, &next.coordinate)
+            }),
+            None => check(index, &|area| is_point_in_area(area, &point.coordinate)),
+        };
+        if all_touched {
+            break;
+        }
+    }
+
+    (touched, first_matched_point)
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use crate::model::Coordinate;
+    use chrono::Duration;
+
+    use crate::io::{read_track_coordinates, read_track_points};
+    use crate::model::{
+        ActivityType, CircleFilter, Coordinate, CorridorFilter, PolygonFilter, SquaredFilter,
+    };
 
     use super::*;
 
     const TEST_FILE: &str = "test/files/9244476879.gpx";
 
+    fn file_contains_point_in_area<A: Clone + Into<AreaFilter>>(path: &Path, area: &A) -> bool {
+        let areas = [NamedArea::new("area", area.clone().into())];
+        let conditions = TrackConditions::default();
+        scan_file(
+            path,
+            &areas.iter().collect::<Vec<_>>(),
+            MatchMode::Points,
+            &conditions,
+        )
+        .unwrap()
+        .touched
+        .contains(&true)
+    }
+
     #[test]
     fn test_file_is_not_in_area() {
         let area = SquaredFilter::new(
@@ -175,4 +670,732 @@ mod tests {
 
         assert_eq!(true, file_contains_point_in_area(&path, &area));
     }
+
+    #[test]
+    fn test_file_is_in_area_across_antimeridian() {
+        let area = SquaredFilter::new(
+            Coordinate::new(-20.0, 160.0),
+            Coordinate::new(-10.0, -170.0),
+        );
+        let path = PathBuf::from(TEST_FILE);
+
+        assert!(file_contains_point_in_area(&path, &area));
+    }
+
+    #[test]
+    fn test_file_is_prefiltered_across_antimeridian() {
+        let areas = [NamedArea::new(
+            "area",
+            SquaredFilter::new(
+                Coordinate::new(-20.0, 170.0),
+                Coordinate::new(-10.0, -170.0),
+            )
+            .into(),
+        )];
+        let path = PathBuf::from(TEST_FILE);
+
+        // The first point at 166.94 is around 330 km west of the box
+        let (in_area, nearby, _) = prefilter_files(
+            vec![path.clone()],
+            &areas,
+            400.0,
+            &TrackConditions::default(),
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+        assert!(in_area.is_empty());
+        assert_eq!(vec![path.clone()], nearby);
+
+        let (in_area, nearby, far) = prefilter_files(
+            vec![path.clone()],
+            &areas,
+            300.0,
+            &TrackConditions::default(),
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+        assert!(in_area.is_empty());
+        assert!(nearby.is_empty());
+        assert_eq!(path, far[0].path);
+        assert!(far[0].areas.is_empty());
+    }
+
+    #[test]
+    fn test_file_crosses_area_between_points() {
+        let path = PathBuf::from("test/files/8651674449.gpx");
+        let coordinates = read_track_coordinates(&path, &[PointKind::Track]).unwrap();
+        // A tiny box in the middle of the line between the first two points
+        let (a, b) = (coordinates[0], coordinates[1]);
+        let middle = Coordinate::new(
+            (a.latitude + b.latitude) / 2.0,
+            (a.longitude + b.longitude) / 2.0,
+        );
+        let areas = [NamedArea::new(
+            "area",
+            SquaredFilter::new(
+                Coordinate::new(middle.latitude - 0.00001, middle.longitude - 0.00001),
+                Coordinate::new(middle.latitude + 0.00001, middle.longitude + 0.00001),
+            )
+            .into(),
+        )];
+
+        let conditions = TrackConditions::default();
+        assert!(!scan_file(
+            &path,
+            &areas.iter().collect::<Vec<_>>(),
+            MatchMode::Points,
+            &conditions
+        )
+        .unwrap()
+        .touched
+        .contains(&true));
+        assert!(scan_file(
+            &path,
+            &areas.iter().collect::<Vec<_>>(),
+            MatchMode::Segments,
+            &conditions
+        )
+        .unwrap()
+        .touched
+        .contains(&true));
+    }
+
+    #[test]
+    fn test_file_is_in_polygon() {
+        let area = PolygonFilter::new(
+            vec![
+                Coordinate::new(-11.64, 166.94),
+                Coordinate::new(-11.64, 166.95),
+                Coordinate::new(-11.63, 166.945),
+            ],
+            vec![],
+        );
+        let path = PathBuf::from(TEST_FILE);
+
+        assert!(file_contains_point_in_area(&path, &area));
+    }
+
+    #[test]
+    fn test_file_reports_area_names() {
+        let areas = [
+            NamedArea::new(
+                "Nuremberg",
+                SquaredFilter::new(
+                    Coordinate::new(49.454470, 10.954986),
+                    Coordinate::new(49.506443, 11.030173),
+                )
+                .into(),
+            ),
+            NamedArea::new(
+                "Vanuatu",
+                SquaredFilter::new(
+                    Coordinate::new(-12.5763870, 165.3753910),
+                    Coordinate::new(-10.985259, 167.48004),
+                )
+                .into(),
+            ),
+        ];
+        let path = PathBuf::from(TEST_FILE);
+
+        let conditions = TrackConditions::default();
+        let areas: Vec<&NamedArea> = areas.iter().collect();
+        let result = scan_file(&path, &areas, MatchMode::Points, &conditions)
+            .unwrap()
+            .into_result(path.clone(), &areas, Instant::now());
+
+        assert_eq!(vec![String::from("Vanuatu")], result.areas);
+        assert_eq!(MatchReason::FullScan, result.reason);
+        assert!(result.statistics.unwrap().distance_m > 0.0);
+        let first_point = read_track_coordinates(&path, &[PointKind::Track]).unwrap()[0];
+        assert_eq!(Some(first_point), result.first_matched_point);
+    }
+
+    #[test]
+    fn test_prefilter_reports_first_point() {
+        let path = PathBuf::from(TEST_FILE);
+        let first_point = read_track_coordinates(&path, &[PointKind::Track]).unwrap()[0];
+        let areas = [NamedArea::new(
+            "start",
+            CircleFilter::new(first_point, 10.0).unwrap().into(),
+        )];
+
+        let (files_area, files_nearby, _) = prefilter_files(
+            vec![path.clone()],
+            &areas,
+            300.0,
+            &TrackConditions::default(),
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+
+        assert!(files_nearby.is_empty());
+        assert_eq!(1, files_area.len());
+        assert_eq!(MatchReason::Prefilter, files_area[0].reason);
+        assert_eq!(Some(first_point), files_area[0].first_matched_point);
+        assert_eq!(vec![String::from("start")], files_area[0].areas);
+        assert_eq!(None, files_area[0].statistics);
+    }
+
+    #[test]
+    fn test_file_reports_all_areas() {
+        let path = PathBuf::from(TEST_FILE);
+        let coordinates = read_track_coordinates(&path, &[PointKind::Track]).unwrap();
+        let around = |coordinate: &Coordinate| -> AreaFilter {
+            CircleFilter::new(*coordinate, 10.0).unwrap().into()
+        };
+        let areas = vec![
+            NamedArea::new("end", around(&coordinates[coordinates.len() - 1])),
+            NamedArea::new("nowhere", around(&Coordinate::new(0.0, 0.0))),
+            NamedArea::new("start", around(&coordinates[0])),
+        ];
+
+        let conditions = TrackConditions::default();
+        let files = filter_tracks_outside_area(
+            vec![path.clone()],
+            &areas,
+            MatchMode::Points,
+            &conditions,
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+
+        assert_eq!(1, files.len());
+        assert_eq!(path, files[0].path);
+        assert_eq!(
+            vec![String::from("end"), String::from("start")],
+            files[0].areas
+        );
+    }
+
+    #[test]
+    fn test_file_matches_expression() {
+        let path = PathBuf::from(TEST_FILE);
+        let coordinates = read_track_coordinates(&path, &[PointKind::Track]).unwrap();
+        let around = |name: &str, coordinate: &Coordinate| -> NamedArea {
+            NamedArea::new(name, CircleFilter::new(*coordinate, 10.0).unwrap().into())
+        };
+        let start = around("start", &coordinates[0]);
+        let end = around("end", &coordinates[coordinates.len() - 1]);
+        let nowhere = around("nowhere", &Coordinate::new(0.0, 0.0));
+        let conditions = TrackConditions::default();
+        let matches = |expression: AreaExpression| {
+            !filter_tracks_by_expression(
+                vec![path.clone()],
+                &expression,
+                MatchMode::Points,
+                &conditions,
+                1,
+                &Mutex::default(),
+            )
+            .unwrap()
+            .is_empty()
+        };
+
+        assert!(matches(AreaExpression::AllOf(vec![
+            AreaExpression::Include(start.clone()),
+            AreaExpression::Include(end.clone()),
+            AreaExpression::Exclude(nowhere.clone()),
+        ])));
+        assert!(matches(AreaExpression::AnyOf(vec![
+            AreaExpression::Include(nowhere.clone()),
+            AreaExpression::Include(end.clone()),
+        ])));
+        assert!(!matches(AreaExpression::AllOf(vec![
+            AreaExpression::Include(start.clone()),
+            AreaExpression::Include(nowhere.clone()),
+        ])));
+        assert!(!matches(AreaExpression::AllOf(vec![
+            AreaExpression::Include(start),
+            AreaExpression::Exclude(end),
+        ])));
+    }
+
+    #[test]
+    fn test_file_spends_time_in_area() {
+        let areas = [NamedArea::new(
+            "Vanuatu",
+            SquaredFilter::new(
+                Coordinate::new(-12.5763870, 165.3753910),
+                Coordinate::new(-10.985259, 167.48004),
+            )
+            .into(),
+        )];
+        let path = PathBuf::from(TEST_FILE);
+        let reaches = |conditions: TrackConditions| {
+            scan_file(
+                &path,
+                &areas.iter().collect::<Vec<_>>(),
+                MatchMode::Points,
+                &conditions,
+            )
+            .unwrap()
+            .touched
+            .contains(&true)
+        };
+
+        assert!(reaches(TrackConditions {
+            min_time_in_area: Some(Duration::minutes(1)),
+            min_distance_in_area_m: Some(100.0),
+            ..Default::default()
+        }));
+        assert!(!reaches(TrackConditions {
+            min_time_in_area: Some(Duration::days(1)),
+            ..Default::default()
+        }));
+        assert!(!reaches(TrackConditions {
+            min_distance_in_area_m: Some(1_000_000.0),
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn test_files_meeting_conditions() {
+        let paths = vec![
+            PathBuf::from(TEST_FILE),
+            PathBuf::from("test/files/8651674449.gpx"),
+        ];
+        let conditions = TrackConditions {
+            after: "2023-03-01T00:00:00+01:00".parse().ok(),
+            ..Default::default()
+        };
+
+        let files =
+            filter_tracks_not_meeting_conditions(paths.clone(), &conditions, 2, &Mutex::default())
+                .unwrap();
+        assert_eq!(vec![paths[1].clone()], files);
+
+        let files = filter_tracks_not_meeting_conditions(
+            paths.clone(),
+            &TrackConditions::default(),
+            2,
+            &Mutex::default(),
+        )
+        .unwrap();
+        assert_eq!(2, files.len());
+
+        let conditions = TrackConditions {
+            activity_types: vec![ActivityType::Walk],
+            ..Default::default()
+        };
+        let files =
+            filter_tracks_not_meeting_conditions(paths, &conditions, 2, &Mutex::default()).unwrap();
+        assert_eq!(vec![PathBuf::from("test/files/8651674449.gpx")], files);
+    }
+
+    #[test]
+    fn test_fit_file_is_in_area() {
+        let path = PathBuf::from("test/files/formats/walk.fit");
+        let around = |coordinate: Coordinate| -> AreaFilter {
+            CircleFilter::new(coordinate, 50.0).unwrap().into()
+        };
+        let areas = [
+            NamedArea::new("start", around(Coordinate::new(49.479887, 10.985473))),
+            NamedArea::new("end", around(Coordinate::new(49.4711, 10.9912))),
+        ];
+
+        let errors = Mutex::default();
+        let (files_area, _, _) = prefilter_files(
+            vec![path.clone()],
+            &areas[..1],
+            300.0,
+            &TrackConditions::default(),
+            1,
+            &errors,
+        )
+        .unwrap();
+        assert_eq!(1, files_area.len());
+        let files = filter_tracks_outside_area(
+            vec![path.clone()],
+            &areas[1..],
+            MatchMode::Points,
+            &TrackConditions::default(),
+            1,
+            &errors,
+        )
+        .unwrap();
+        assert_eq!(1, files.len());
+        let statistics = files[0].statistics.as_ref().unwrap();
+        assert_eq!(Some(Duration::seconds(610)), statistics.duration);
+        assert!(errors.into_inner().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_route_and_waypoints_are_matched_by_kind() {
+        let path = PathBuf::from("test/files/formats/plan.gpx");
+        let areas = [
+            NamedArea::new(
+                "castle",
+                CircleFilter::new(Coordinate::new(49.45, 11.07), 50.0)
+                    .unwrap()
+                    .into(),
+            ),
+            NamedArea::new(
+                "bridge",
+                CircleFilter::new(Coordinate::new(49.48, 11.08), 50.0)
+                    .unwrap()
+                    .into(),
+            ),
+        ];
+        let scan = |kinds: Vec<PointKind>, mode: MatchMode| {
+            let conditions = TrackConditions {
+                point_kinds: kinds,
+                ..Default::default()
+            };
+            filter_tracks_outside_area(
+                vec![path.clone()],
+                &areas,
+                mode,
+                &conditions,
+                1,
+                &Mutex::default(),
+            )
+            .unwrap()
+        };
+
+        // The track passes between both areas and its points are the only ones checked by default
+        assert!(scan(Vec::new(), MatchMode::Segments).is_empty());
+
+        let files = scan(vec![PointKind::Route], MatchMode::Segments);
+        assert_eq!(vec![String::from("bridge")], files[0].areas);
+        assert_eq!(Some(PointKind::Route), files[0].matched_kind);
+        assert!(scan(vec![PointKind::Route], MatchMode::Points).is_empty());
+
+        // The segment from the waypoint to the first route point is not a line
+        let files = scan(
+            vec![PointKind::Waypoint, PointKind::Route],
+            MatchMode::Segments,
+        );
+        assert_eq!(2, files[0].areas.len());
+        assert_eq!(Some(PointKind::Waypoint), files[0].matched_kind);
+
+        let conditions = TrackConditions {
+            point_kinds: vec![PointKind::Waypoint],
+            ..Default::default()
+        };
+        let (files_area, _, _) = prefilter_files(
+            vec![path.clone()],
+            &areas,
+            300.0,
+            &conditions,
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+        assert_eq!(Some(PointKind::Waypoint), files_area[0].matched_kind);
+
+        // The waypoint far from the start of the track does not hide it
+        let start = [NamedArea::new(
+            "start",
+            CircleFilter::new(Coordinate::new(49.40, 11.00), 50.0)
+                .unwrap()
+                .into(),
+        )];
+        let conditions = TrackConditions {
+            point_kinds: vec![PointKind::Waypoint, PointKind::Track],
+            ..Default::default()
+        };
+        let (files_area, _, _) = prefilter_files(
+            vec![path.clone()],
+            &start,
+            3.0,
+            &conditions,
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+        assert_eq!(Some(PointKind::Track), files_area[0].matched_kind);
+    }
+
+    #[test]
+    fn test_segments_do_not_join_lines() {
+        let areas = [NamedArea::new(
+            "bridge",
+            CircleFilter::new(Coordinate::new(49.48, 11.08), 50.0)
+                .unwrap()
+                .into(),
+        )];
+        let point = |latitude: f32, line: usize| TrackPoint {
+            kind: PointKind::Route,
+            line,
+            ..TrackPoint::new(Coordinate::new(latitude, 11.08), None)
+        };
+        let touches = |points: &[TrackPoint]| {
+            find_touched_areas(
+                points,
+                &areas.iter().collect::<Vec<_>>(),
+                MatchMode::Segments,
+            )
+            .0 == [true]
+        };
+
+        // The end of a route and the start of the next one are on both sides of the bridge
+        assert!(touches(&[point(49.46, 0), point(49.50, 0)]));
+        assert!(!touches(&[point(49.46, 0), point(49.50, 1)]));
+    }
+
+    #[test]
+    fn test_tcx_file_is_in_area() {
+        let path = PathBuf::from("test/files/formats/ride.tcx");
+        let area = |coordinate: Coordinate| -> AreaFilter {
+            CircleFilter::new(coordinate, 50.0).unwrap().into()
+        };
+        let areas = [
+            NamedArea::new("start", area(Coordinate::new(49.479887, 10.985473))),
+            NamedArea::new("end", area(Coordinate::new(49.4711, 10.9912))),
+        ];
+
+        let errors = Mutex::default();
+        let (files_area, _, _) = prefilter_files(
+            vec![path.clone()],
+            &areas[..1],
+            300.0,
+            &TrackConditions::default(),
+            1,
+            &errors,
+        )
+        .unwrap();
+        assert_eq!(1, files_area.len());
+        let files = filter_tracks_outside_area(
+            vec![path.clone()],
+            &areas[1..],
+            MatchMode::Points,
+            &TrackConditions::default(),
+            1,
+            &errors,
+        )
+        .unwrap();
+        assert_eq!(1, files.len());
+
+        // Only the .gpx files have other kinds of points
+        let conditions = TrackConditions {
+            point_kinds: vec![PointKind::Route],
+            ..Default::default()
+        };
+        let (files_area, _, _) =
+            prefilter_files(vec![path.clone()], &areas, 300.0, &conditions, 1, &errors).unwrap();
+        assert_eq!(1, files_area.len());
+        let files = filter_tracks_outside_area(
+            vec![path.clone()],
+            &areas[1..],
+            MatchMode::Points,
+            &conditions,
+            1,
+            &errors,
+        )
+        .unwrap();
+        assert_eq!(1, files.len());
+        assert!(errors.into_inner().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_geojson_file_is_in_area() {
+        let path = PathBuf::from("test/files/formats/ride.geojson");
+        let areas = [NamedArea::new(
+            "start",
+            CircleFilter::new(Coordinate::new(49.479887, 10.985473), 50.0)
+                .unwrap()
+                .into(),
+        )];
+
+        let errors = Mutex::default();
+        let (files_area, _, _) = prefilter_files(
+            vec![path.clone()],
+            &areas,
+            300.0,
+            &TrackConditions::default(),
+            1,
+            &errors,
+        )
+        .unwrap();
+        assert_eq!(1, files_area.len());
+        let properties = files_area[0].properties.as_ref().unwrap();
+        assert_eq!(Some("Derived ride"), properties["name"].as_str());
+
+        // The full scan keeps them as well, without reading the file again
+        let files = filter_tracks_outside_area(
+            vec![path.clone()],
+            &areas,
+            MatchMode::Points,
+            &TrackConditions::default(),
+            1,
+            &errors,
+        )
+        .unwrap();
+        assert_eq!(files_area[0].properties, files[0].properties);
+        assert!(errors.into_inner().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_file_without_first_point_is_scanned() {
+        let path = std::env::temp_dir().join("gpx-geo-filter-multiline.gpx");
+        let content = r#"<gpx><trk><trkseg>
+ <trkpt
+  lat="49.40" lon="11.00"/>
+</trkseg></trk></gpx>"#;
+        std::fs::write(&path, content).unwrap();
+        let areas = [NamedArea::new(
+            "nowhere",
+            CircleFilter::new(Coordinate::new(0.0, 0.0), 50.0)
+                .unwrap()
+                .into(),
+        )];
+
+        let (files_area, files_nearby, _) = prefilter_files(
+            vec![path.clone()],
+            &areas,
+            300.0,
+            &TrackConditions::default(),
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+        assert!(files_area.is_empty());
+        assert_eq!(vec![path.clone()], files_nearby);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_unreadable_files_are_reported() {
+        let missing = PathBuf::from("test/files/missing.gpx");
+        let paths = vec![PathBuf::from(TEST_FILE), missing.clone()];
+        let areas = [NamedArea::new(
+            "Vanuatu",
+            SquaredFilter::new(
+                Coordinate::new(-12.5763870, 165.3753910),
+                Coordinate::new(-10.985259, 167.48004),
+            )
+            .into(),
+        )];
+
+        let errors = Mutex::default();
+        let (files_area, files_nearby, files_far) = prefilter_files(
+            paths,
+            &areas,
+            300.0,
+            &TrackConditions::default(),
+            2,
+            &errors,
+        )
+        .unwrap();
+
+        assert_eq!(1, files_area.len());
+        assert!(files_nearby.is_empty());
+        // The unreadable file is only reported as an error, not as far from the areas
+        assert!(files_far.is_empty());
+        let errors = errors.into_inner().unwrap();
+        assert_eq!(1, errors.len());
+        assert_eq!(Some(&missing), errors[0].path());
+    }
+
+    #[test]
+    fn test_file_follows_sequence() {
+        let path = PathBuf::from("test/files/8651674449.gpx");
+        let points = read_track_points(&path).unwrap();
+        let around = |name: &str, point: &TrackPoint| -> NamedArea {
+            NamedArea::new(
+                name,
+                CircleFilter::new(point.coordinate, 5.0).unwrap().into(),
+            )
+        };
+        let first = around("first", &points[100]);
+        let second = around("second", &points[1000]);
+
+        let forward = [first.clone(), second.clone()];
+        let files = filter_tracks_not_following_sequence(
+            vec![path.clone()],
+            &forward,
+            MatchMode::Points,
+            &TrackConditions::default(),
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+        assert_eq!(1, files.len());
+        let entries = &files[0].entries;
+        assert_eq!(2, entries.len());
+        assert_eq!(vec!["first", "second"], files[0].areas);
+        assert!(entries[0].time.unwrap() <= points[100].time.unwrap());
+        assert!(entries[1].time.unwrap() > entries[0].time.unwrap());
+
+        let backward = [second, first];
+        let files = filter_tracks_not_following_sequence(
+            vec![path.clone()],
+            &backward,
+            MatchMode::Points,
+            &TrackConditions::default(),
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+        assert!(files.is_empty());
+    }
+
+    #[test]
+    fn test_sequence_is_entered_between_points() {
+        let points = [
+            TrackPoint::new(Coordinate::new(49.40, 11.00), None),
+            TrackPoint::new(Coordinate::new(49.40, 11.02), None),
+            TrackPoint {
+                line: 1,
+                ..TrackPoint::new(Coordinate::new(49.40, 11.04), None)
+            },
+        ];
+        let around = |name: &str, longitude: f32| -> NamedArea {
+            let center = Coordinate::new(49.40, longitude);
+            NamedArea::new(name, CircleFilter::new(center, 100.0).unwrap().into())
+        };
+        let areas = [around("start", 11.00), around("middle", 11.01)];
+
+        assert_eq!(None, find_area_sequence(&points, &areas, MatchMode::Points));
+        let entries = find_area_sequence(&points, &areas, MatchMode::Segments).unwrap();
+        assert_eq!(vec![points[0], points[0]], entries);
+
+        // The second line does not join the first one
+        let areas = [around("start", 11.00), around("gap", 11.03)];
+        assert_eq!(
+            None,
+            find_area_sequence(&points, &areas, MatchMode::Segments)
+        );
+    }
+
+    #[test]
+    fn test_file_follows_corridor() {
+        let path = PathBuf::from("test/files/8651674449.gpx");
+        let route = read_track_coordinates(&path, &[PointKind::Track]).unwrap();
+        // Around 45% of the length of the walk is in its first 500 points
+        let corridor = NamedArea::new(
+            "corridor",
+            CorridorFilter::new(route[..500].to_vec(), 20.0)
+                .unwrap()
+                .into(),
+        );
+
+        let files = filter_tracks_mostly_outside_area(
+            vec![path.clone()],
+            &corridor,
+            40.0,
+            &TrackConditions::default(),
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+        assert_eq!(1, files.len());
+        assert_eq!(path, files[0].path);
+
+        let files = filter_tracks_mostly_outside_area(
+            vec![path.clone()],
+            &corridor,
+            50.0,
+            &TrackConditions::default(),
+            1,
+            &Mutex::default(),
+        )
+        .unwrap();
+        assert!(files.is_empty());
+    }
 }
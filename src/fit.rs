@@ -0,0 +1,356 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    error::{Error, Result},
+    model::{ActivityType, Coordinate, TrackPoint},
+};
+
+/// Seconds between the Unix epoch and the FIT one, 1989-12-31T00:00:00Z
+const FIT_EPOCH_S: i64 = 631_065_600;
+const SEMICIRCLES_TO_DEGREES: f64 = 180.0 / 2_147_483_648.0;
+
+// Global numbers of the messages that are read, the rest are skipped
+const FILE_ID: u16 = 0;
+const SPORT: u16 = 12;
+const SESSION: u16 = 18;
+const RECORD: u16 = 20;
+
+const TIMESTAMP_FIELD: u8 = 253;
+
+/// Content of a FIT activity that is used to filter it
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct FitActivity {
+    /// Time of the first record or otherwise the time the file was created
+    pub start_time: Option<DateTime<Utc>>,
+    pub activity_type: Option<ActivityType>,
+    pub points: Vec<TrackPoint>,
+}
+
+/// Decode the records with a position of the FIT file, converting the semicircles to degrees and
+/// keeping their time and altitude
+pub fn read_fit<R: Read>(reader: R, path: &Path) -> Result<FitActivity> {
+    let mut decoder = FitDecoder::new(reader, path)?;
+    let mut activity = FitActivity::default();
+    let mut time_created = None;
+
+    while let Some(message) = decoder.next_message()? {
+        match message.global {
+            FILE_ID => time_created = message.field(4).map(fit_time),
+            SPORT if activity.activity_type.is_none() => {
+                activity.activity_type = message.field(0).map(sport_to_activity_type)
+            }
+            SESSION if activity.activity_type.is_none() => {
+                activity.activity_type = message.field(5).map(sport_to_activity_type)
+            }
+            RECORD => activity.points.extend(record_to_point(&message)),
+            _ => (),
+        }
+    }
+
+    activity.start_time = activity
+        .points
+        .iter()
+        .find_map(|point| point.time)
+        .or(time_created);
+    Ok(activity)
+}
+
+/// Decode the file only until the first record with a position
+pub fn read_first_fit_coordinate<R: Read>(reader: R, path: &Path) -> Result<Option<Coordinate>> {
+    let mut decoder = FitDecoder::new(reader, path)?;
+    while let Some(message) = decoder.next_message()? {
+        if message.global == RECORD {
+            if let Some(point) = record_to_point(&message) {
+                return Ok(Some(point.coordinate));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn record_to_point(message: &Message) -> Option<TrackPoint> {
+    let latitude = message.field(0)? as f64 * SEMICIRCLES_TO_DEGREES;
+    let longitude = message.field(1)? as f64 * SEMICIRCLES_TO_DEGREES;
+    // Both altitudes are in 1/5 m with an offset of 500 m
+    let altitude = message.field(78).or_else(|| message.field(2));
+
+    let coordinate = Coordinate::new(latitude as f32, longitude as f32);
+    Some(TrackPoint {
+        elevation: altitude.map(|altitude| altitude as f64 / 5.0 - 500.0),
+        ..TrackPoint::new(coordinate, message.field(TIMESTAMP_FIELD).map(fit_time))
+    })
+}
+
+fn fit_time(timestamp: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(FIT_EPOCH_S + timestamp, 0).unwrap_or_default()
+}
+
+fn sport_to_activity_type(sport: i64) -> ActivityType {
+    match sport {
+        1 => ActivityType::Run,
+        2 => ActivityType::Ride,
+        5 => ActivityType::Swim,
+        11 => ActivityType::Walk,
+        17 => ActivityType::Hike,
+        other => ActivityType::Other(format!("fit sport {other}")),
+    }
+}
+
+struct FieldDefinition {
+    number: u8,
+    size: usize,
+    base_type: u8,
+}
+
+struct MessageDefinition {
+    global: u16,
+    big_endian: bool,
+    fields: Vec<FieldDefinition>,
+    /// Total size of the developer fields, which are skipped
+    developer_size: usize,
+}
+
+/// Data message with the integer fields that have a valid value
+struct Message {
+    global: u16,
+    fields: Vec<(u8, i64)>,
+}
+
+impl Message {
+    fn field(&self, number: u8) -> Option<i64> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == number)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Reader of the messages of a FIT file, keeping the definitions of the local message types
+struct FitDecoder<R> {
+    reader: R,
+    path: PathBuf,
+    /// Bytes of records left before the CRC at the end of the file
+    remaining: u64,
+    definitions: Vec<Option<MessageDefinition>>,
+    last_timestamp: u32,
+}
+
+impl<R: Read> FitDecoder<R> {
+    fn new(reader: R, path: &Path) -> Result<FitDecoder<R>> {
+        let mut decoder = FitDecoder {
+            reader,
+            path: path.to_path_buf(),
+            remaining: u64::MAX,
+            definitions: (0..16).map(|_| None).collect(),
+            last_timestamp: 0,
+        };
+
+        let header_size = decoder.read_bytes(1)?[0] as usize;
+        if header_size != 12 && header_size != 14 {
+            return Err(decoder.error("the file does not start with a FIT header"));
+        }
+        let header = decoder.read_bytes(header_size - 1)?;
+        if &header[7..11] != b".FIT" {
+            return Err(decoder.error("the file does not start with a FIT header"));
+        }
+        decoder.remaining = u32::from_le_bytes([header[3], header[4], header[5], header[6]]) as u64;
+        Ok(decoder)
+    }
+
+    /// Next data message, reading the definitions found before it
+    fn next_message(&mut self) -> Result<Option<Message>> {
+        while self.remaining > 0 {
+            let header = self.read_bytes(1)?[0];
+            if header & 0x80 != 0 {
+                // Compressed timestamp header, the offset replaces the last 5 bits of the last one
+                let offset = (header & 0x1F) as u32;
+                let mut timestamp = (self.last_timestamp & !0x1F) | offset;
+                if offset < self.last_timestamp & 0x1F {
+                    timestamp += 0x20;
+                }
+                self.last_timestamp = timestamp;
+
+                let mut message = self.read_data((header >> 5) & 0x03)?;
+                message.fields.push((TIMESTAMP_FIELD, timestamp as i64));
+                return Ok(Some(message));
+            } else if header & 0x40 != 0 {
+                self.read_definition(header & 0x0F, header & 0x20 != 0)?;
+            } else {
+                return self.read_data(header & 0x0F).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_definition(&mut self, local: u8, has_developer_fields: bool) -> Result<()> {
+        let content = self.read_bytes(5)?;
+        let big_endian = content[1] == 1;
+        let global = match big_endian {
+            true => u16::from_be_bytes([content[2], content[3]]),
+            false => u16::from_le_bytes([content[2], content[3]]),
+        };
+
+        let fields = self
+            .read_bytes(3 * content[4] as usize)?
+            .chunks(3)
+            .map(|field| FieldDefinition {
+                number: field[0],
+                size: field[1] as usize,
+                base_type: field[2],
+            })
+            .collect();
+        let mut developer_size = 0;
+        if has_developer_fields {
+            let count = self.read_bytes(1)?[0] as usize;
+            developer_size = self
+                .read_bytes(3 * count)?
+                .chunks(3)
+                .map(|field| field[1] as usize)
+                .sum();
+        }
+
+        self.definitions[local as usize] = Some(MessageDefinition {
+            global,
+            big_endian,
+            fields,
+            developer_size,
+        });
+        Ok(())
+    }
+
+    fn read_data(&mut self, local: u8) -> Result<Message> {
+        let Some(definition) = self.definitions[local as usize].take() else {
+            return Err(self.error(&format!("message of undefined local type {local}")));
+        };
+
+        let mut message = Message {
+            global: definition.global,
+            fields: Vec::new(),
+        };
+        for field in &definition.fields {
+            let bytes = self.read_bytes(field.size)?;
+            if let Some(value) = field_value(&bytes, field.base_type, definition.big_endian) {
+                message.fields.push((field.number, value));
+            }
+        }
+        self.read_bytes(definition.developer_size)?;
+
+        if let Some(timestamp) = message.field(TIMESTAMP_FIELD) {
+            self.last_timestamp = timestamp as u32;
+        }
+        self.definitions[local as usize] = Some(definition);
+        Ok(message)
+    }
+
+    fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>> {
+        if size as u64 > self.remaining {
+            return Err(self.error("the records go past the size in the header"));
+        }
+        let mut bytes = vec![0; size];
+        self.reader
+            .read_exact(&mut bytes)
+            .map_err(|err| Error::io(&self.path, err))?;
+        self.remaining -= size as u64;
+        Ok(bytes)
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::Fit {
+            path: self.path.clone(),
+            message: String::from(message),
+        }
+    }
+}
+
+/// Integer value of a field, `None` for arrays, strings, floats and the invalid value of the type
+fn field_value(bytes: &[u8], base_type: u8, big_endian: bool) -> Option<i64> {
+    // Size, whether it is signed and the value that means invalid of every integer type
+    let (size, signed, invalid): (usize, bool, u64) = match base_type & 0x1F {
+        0 | 2 | 13 => (1, false, 0xFF),
+        1 => (1, true, 0x7F),
+        3 => (2, true, 0x7FFF),
+        4 => (2, false, 0xFFFF),
+        5 => (4, true, 0x7FFF_FFFF),
+        6 => (4, false, 0xFFFF_FFFF),
+        10 => (1, false, 0),
+        11 => (2, false, 0),
+        12 => (4, false, 0),
+        14 => (8, true, 0x7FFF_FFFF_FFFF_FFFF),
+        15 => (8, false, u64::MAX),
+        16 => (8, false, 0),
+        _ => return None,
+    };
+    if bytes.len() != size {
+        return None;
+    }
+
+    let mut raw: u64 = 0;
+    for (index, byte) in bytes.iter().enumerate() {
+        let shift = match big_endian {
+            true => 8 * (size - 1 - index),
+            false => 8 * index,
+        };
+        raw |= (*byte as u64) << shift;
+    }
+    if raw == invalid {
+        return None;
+    }
+
+    match signed {
+        // Extend the sign of the smaller types
+        true => Some(((raw << (64 - 8 * size)) as i64) >> (64 - 8 * size)),
+        false => Some(raw as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FILE: &str = "test/files/formats/walk.fit";
+
+    #[test]
+    fn decode_activity() {
+        let fit = std::fs::read(TEST_FILE).unwrap();
+
+        let activity = read_fit(&fit[..], Path::new(TEST_FILE)).unwrap();
+
+        assert_eq!(Some(ActivityType::Walk), activity.activity_type);
+        assert_eq!(3, activity.points.len());
+        let first = &activity.points[0];
+        assert!((first.coordinate.latitude - 49.479887).abs() < 1e-5);
+        assert!((first.coordinate.longitude - 10.985473).abs() < 1e-5);
+        assert_eq!(Some(310.0), first.elevation);
+        assert_eq!(
+            "2021-09-08T01:46:40+00:00",
+            activity.start_time.unwrap().to_rfc3339()
+        );
+        // The last record has a compressed timestamp
+        assert_eq!(
+            activity.start_time.unwrap() + chrono::Duration::seconds(610),
+            activity.points[2].time.unwrap()
+        );
+
+        let first_coordinate = read_first_fit_coordinate(&fit[..], Path::new(TEST_FILE));
+        assert_eq!(Some(first.coordinate), first_coordinate.unwrap());
+    }
+
+    #[test]
+    fn decode_invalid_file() {
+        let fit = std::fs::read(TEST_FILE).unwrap();
+
+        assert!(matches!(
+            read_fit(&b"<gpx></gpx>"[..], Path::new("a.fit")),
+            Err(Error::Fit { .. })
+        ));
+        assert!(matches!(
+            read_fit(&fit[..fit.len() - 8], Path::new("a.fit")),
+            Err(Error::Io { .. })
+        ));
+    }
+}
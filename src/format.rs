@@ -0,0 +1,80 @@
+use std::path::Path;
+
+/// Compression of a track file, known from its last extension
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Zstd,
+}
+
+impl Compression {
+    pub fn of(path: &Path) -> Compression {
+        match extension(path).as_deref() {
+            Some("gz") => Compression::Gzip,
+            Some("bz2") => Compression::Bzip2,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Format of the content of a track file, known from the extension below the one of the
+/// compression, e.g. `.fit` for `activity.fit.gz`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrackFormat {
+    Gpx,
+    Fit,
+    Tcx,
+    Kml,
+    /// A zipped .kml document
+    Kmz,
+    GeoJson,
+}
+
+impl TrackFormat {
+    /// The files with any other extension, or none, are not tracks
+    pub fn of(path: &Path) -> Option<TrackFormat> {
+        let uncompressed = match Compression::of(path) {
+            Compression::None => path,
+            _ => Path::new(path.file_stem()?),
+        };
+        match extension(uncompressed).as_deref() {
+            Some("gpx") => Some(TrackFormat::Gpx),
+            Some("fit") => Some(TrackFormat::Fit),
+            Some("tcx") => Some(TrackFormat::Tcx),
+            Some("kml") => Some(TrackFormat::Kml),
+            Some("kmz") => Some(TrackFormat::Kmz),
+            Some("geojson") => Some(TrackFormat::GeoJson),
+            _ => None,
+        }
+    }
+}
+
+/// Lowercase last extension of the file
+pub fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_of_compressed_files() {
+        let format = |name: &str| TrackFormat::of(Path::new(name));
+
+        assert_eq!(Some(TrackFormat::Gpx), format("a/1.GPX"));
+        assert_eq!(Some(TrackFormat::Gpx), format("1.gpx.gz"));
+        assert_eq!(Some(TrackFormat::Fit), format("1.fit.zst"));
+        assert_eq!(Some(TrackFormat::Tcx), format("1.tcx"));
+        assert_eq!(Some(TrackFormat::Kmz), format("Rides.KMZ"));
+        assert_eq!(Some(TrackFormat::GeoJson), format("ride.geojson.gz"));
+        assert_eq!(None, format("ride.json"));
+        assert_eq!(None, format("1.gz"));
+        assert_eq!(None, format("README"));
+        assert_eq!(Compression::Bzip2, Compression::of(Path::new("1.fit.bz2")));
+    }
+}
@@ -0,0 +1,299 @@
+use crate::model::{Coordinate, SquaredFilter};
+
+/// Mean radius of the earth in meters (IUGG)
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Semi-major axis of the WGS84 ellipsoid in meters
+const WGS84_A: f64 = 6_378_137.0;
+/// Flattening of the WGS84 ellipsoid
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+/// Semi-minor axis of the WGS84 ellipsoid in meters
+const WGS84_B: f64 = WGS84_A * (1.0 - WGS84_F);
+
+/// Great-circle distance in meters between two coordinates using the haversine formula, which is
+/// precise to around 0.5% for any pair of points on earth
+pub fn haversine_distance_in_m(a: &Coordinate, b: &Coordinate) -> f64 {
+    let latitude_a = (a.latitude as f64).to_radians();
+    let latitude_b = (b.latitude as f64).to_radians();
+    let delta_latitude = latitude_b - latitude_a;
+    let delta_longitude = (b.longitude as f64 - a.longitude as f64).to_radians();
+
+    let h = (delta_latitude / 2.0).sin().powi(2)
+        + latitude_a.cos() * latitude_b.cos() * (delta_longitude / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+}
+
+/// Distance in meters between two coordinates on the WGS84 ellipsoid using the inverse formula of
+/// Vincenty, which is precise to less than a millimeter. The formula does not converge for nearly
+/// antipodal points, where the haversine distance is returned instead
+pub fn vincenty_distance_in_m(a: &Coordinate, b: &Coordinate) -> f64 {
+    const MAX_ITERATIONS: usize = 200;
+    const TOLERANCE: f64 = 1e-12;
+
+    let delta_longitude = (b.longitude as f64 - a.longitude as f64).to_radians();
+    let reduced_latitude_a = ((1.0 - WGS84_F) * (a.latitude as f64).to_radians().tan()).atan();
+    let reduced_latitude_b = ((1.0 - WGS84_F) * (b.latitude as f64).to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = reduced_latitude_a.sin_cos();
+    let (sin_u2, cos_u2) = reduced_latitude_b.sin_cos();
+
+    let mut lambda = delta_longitude;
+    for _ in 0..MAX_ITERATIONS {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+            + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2))
+        .sqrt();
+        if sin_sigma == 0.0 {
+            return 0.0; // Same point
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos_squared_alpha = 1.0 - sin_alpha.powi(2);
+        let cos_2_sigma_m = if cos_squared_alpha == 0.0 {
+            0.0 // Both points on the equator
+        } else {
+            cos_sigma - 2.0 * sin_u1 * sin_u2 / cos_squared_alpha
+        };
+        let c =
+            WGS84_F / 16.0 * cos_squared_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos_squared_alpha));
+
+        let previous_lambda = lambda;
+        lambda = delta_longitude
+            + (1.0 - c)
+                * WGS84_F
+                * sin_alpha
+                * (sigma
+                    + c * sin_sigma
+                        * (cos_2_sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2_sigma_m.powi(2))));
+
+        if (lambda - previous_lambda).abs() < TOLERANCE {
+            let u_squared =
+                cos_squared_alpha * (WGS84_A.powi(2) - WGS84_B.powi(2)) / WGS84_B.powi(2);
+            let big_a = 1.0
+                + u_squared / 16384.0
+                    * (4096.0 + u_squared * (-768.0 + u_squared * (320.0 - 175.0 * u_squared)));
+            let big_b = u_squared / 1024.0
+                * (256.0 + u_squared * (-128.0 + u_squared * (74.0 - 47.0 * u_squared)));
+            let delta_sigma = big_b
+                * sin_sigma
+                * (cos_2_sigma_m
+                    + big_b / 4.0
+                        * (cos_sigma * (-1.0 + 2.0 * cos_2_sigma_m.powi(2))
+                            - big_b / 6.0
+                                * cos_2_sigma_m
+                                * (-3.0 + 4.0 * sin_sigma.powi(2))
+                                * (-3.0 + 4.0 * cos_2_sigma_m.powi(2))));
+
+            return WGS84_B * big_a * (sigma - delta_sigma);
+        }
+    }
+
+    haversine_distance_in_m(a, b)
+}
+
+/// Distance in meters from a point to the closest point of the box, 0 if the point is inside. On
+/// the east and west edges the closest point is the foot of the great circle perpendicular to the
+/// meridian of the edge, which is not necessarily at the same latitude as the point
+pub fn point_to_box_distance_in_m(point: &Coordinate, area: &SquaredFilter) -> f64 {
+    let south = area.bottom_left.latitude;
+    let north = area.top_right.latitude;
+    let west = area.bottom_left.longitude;
+    let east = area.top_right.longitude;
+
+    if area.contains_longitude(point.longitude) || point.longitude == east {
+        let latitude = point.latitude.clamp(south, north);
+        return haversine_distance_in_m(point, &Coordinate::new(latitude, point.longitude));
+    }
+
+    let distance_to_west = normalize_longitude(west as f64 - point.longitude as f64).abs();
+    let distance_to_east = normalize_longitude(point.longitude as f64 - east as f64).abs();
+    let (edge, delta_longitude) = match distance_to_west < distance_to_east {
+        true => (west, distance_to_west),
+        false => (east, distance_to_east),
+    };
+
+    let closest = if delta_longitude < 90.0 {
+        let foot_latitude = ((point.latitude as f64).to_radians().tan()
+            / delta_longitude.to_radians().cos())
+        .atan()
+        .to_degrees() as f32;
+        Coordinate::new(foot_latitude.clamp(south, north), edge)
+    } else {
+        // Beyond a quarter of the globe the closest point of the edge is one of its ends
+        let to_south = Coordinate::new(south, edge);
+        let to_north = Coordinate::new(north, edge);
+        match haversine_distance_in_m(point, &to_south) < haversine_distance_in_m(point, &to_north)
+        {
+            true => to_south,
+            false => to_north,
+        }
+    };
+
+    haversine_distance_in_m(point, &closest)
+}
+
+/// Bring a difference of longitudes to the range [-180, 180]
+fn normalize_longitude(delta_longitude: f64) -> f64 {
+    let delta_longitude = (delta_longitude + 180.0).rem_euclid(360.0) - 180.0;
+    match delta_longitude == -180.0 {
+        true => 180.0,
+        false => delta_longitude,
+    }
+}
+
+/// Distance in meters from a point to the segment between `a` and `b`. The coordinates are
+/// projected on a plane tangent to the earth at the point, which is precise for segments of a few
+/// kilometers
+pub fn point_to_segment_distance_in_m(point: &Coordinate, a: &Coordinate, b: &Coordinate) -> f64 {
+    let (ax, ay) = project_around(point, a);
+    let (bx, by) = project_around(point, b);
+
+    let (dx, dy) = (bx - ax, by - ay);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        (-(ax * dx + ay * dy) / length_squared).clamp(0.0, 1.0)
+    };
+
+    ((ax + t * dx).powi(2) + (ay + t * dy).powi(2)).sqrt()
+}
+
+/// Equirectangular projection in meters of `point` on a plane centered at `origin`
+fn project_around(origin: &Coordinate, point: &Coordinate) -> (f64, f64) {
+    let delta_longitude = normalize_longitude(point.longitude as f64 - origin.longitude as f64);
+    let delta_latitude = point.latitude as f64 - origin.latitude as f64;
+
+    let x = delta_longitude.to_radians() * (origin.latitude as f64).to_radians().cos();
+    let y = delta_latitude.to_radians();
+    (x * EARTH_RADIUS_M, y * EARTH_RADIUS_M)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn haversine_paris_london() {
+        let paris = Coordinate::new(48.8566, 2.3522);
+        let london = Coordinate::new(51.5074, -0.1278);
+
+        let distance = haversine_distance_in_m(&paris, &london);
+
+        assert!((distance - 343_560.0).abs() < 100.0, "{distance}");
+    }
+
+    #[test]
+    fn haversine_across_antimeridian() {
+        let west = Coordinate::new(0.0, 179.5);
+        let east = Coordinate::new(0.0, -179.5);
+
+        let distance = haversine_distance_in_m(&west, &east);
+
+        assert!((distance - 111_195.0).abs() < 10.0, "{distance}");
+    }
+
+    #[test]
+    fn haversine_same_point() {
+        let point = Coordinate::new(49.4798870, 10.9854730);
+
+        assert_eq!(0.0, haversine_distance_in_m(&point, &point));
+    }
+
+    #[test]
+    fn vincenty_flinders_peak_buninyong() {
+        // Reference geodesic from the original paper of Vincenty (1975)
+        let flinders_peak = Coordinate::new(-37.951_033_42, 144.424_867_89);
+        let buninyong = Coordinate::new(-37.652_821_14, 143.926_495_53);
+
+        let distance = vincenty_distance_in_m(&flinders_peak, &buninyong);
+
+        assert!((distance - 54_972.271).abs() < 2.0, "{distance}");
+    }
+
+    #[test]
+    fn vincenty_quarter_of_meridian_and_equator() {
+        let origin = Coordinate::new(0.0, 0.0);
+
+        let meridian = vincenty_distance_in_m(&origin, &Coordinate::new(90.0, 0.0));
+        assert!((meridian - 10_001_965.729).abs() < 0.01, "{meridian}");
+
+        let equator = vincenty_distance_in_m(&origin, &Coordinate::new(0.0, 90.0));
+        assert!((equator - 10_018_754.171).abs() < 0.01, "{equator}");
+    }
+
+    #[test]
+    fn vincenty_nearly_antipodal_points() {
+        let a = Coordinate::new(0.0, 0.0);
+        let b = Coordinate::new(0.5, 179.7);
+
+        let distance = vincenty_distance_in_m(&a, &b);
+        let haversine = haversine_distance_in_m(&a, &b);
+
+        assert!(
+            (distance - haversine).abs() / haversine < 0.005,
+            "{distance}"
+        );
+    }
+
+    #[test]
+    fn distance_to_box() {
+        let area = SquaredFilter::new(Coordinate::new(40.0, 10.0), Coordinate::new(50.0, 20.0));
+
+        assert_eq!(
+            0.0,
+            point_to_box_distance_in_m(&Coordinate::new(45.0, 15.0), &area)
+        );
+
+        // One degree north of the box along the meridian
+        let distance = point_to_box_distance_in_m(&Coordinate::new(51.0, 15.0), &area);
+        assert!((distance - 111_195.0).abs() < 10.0, "{distance}");
+
+        // East of the box the closest point of the edge is slightly closer to the pole
+        let point = Coordinate::new(45.0, 25.0);
+        let distance = point_to_box_distance_in_m(&point, &area);
+        let same_latitude = haversine_distance_in_m(&point, &Coordinate::new(45.0, 20.0));
+        assert!(distance < same_latitude, "{distance} {same_latitude}");
+        assert!((distance - 392_884.0).abs() < 10.0, "{distance}");
+
+        // The shortest way from the other side of the antimeridian
+        let distance = point_to_box_distance_in_m(&Coordinate::new(45.0, -170.0), &area);
+        let west_edge = point_to_box_distance_in_m(&Coordinate::new(45.0, -170.0 + 360.0), &area);
+        assert!((distance - west_edge).abs() < 1.0, "{distance} {west_edge}");
+
+        // Inside and next to a box that wraps around the antimeridian
+        let fiji = SquaredFilter::new(
+            Coordinate::new(-21.0, 176.0),
+            Coordinate::new(-12.0, -178.0),
+        );
+        assert_eq!(
+            0.0,
+            point_to_box_distance_in_m(&Coordinate::new(-17.0, 179.9), &fiji)
+        );
+        assert_eq!(
+            0.0,
+            point_to_box_distance_in_m(&Coordinate::new(-17.0, -179.0), &fiji)
+        );
+        let distance = point_to_box_distance_in_m(&Coordinate::new(-17.0, 175.0), &fiji);
+        assert!((distance - 106_337.0).abs() < 100.0, "{distance}");
+    }
+
+    #[test]
+    fn distance_to_segment() {
+        let a = Coordinate::new(0.0, 0.0);
+        let b = Coordinate::new(0.0, 0.01);
+
+        // Perpendicular to the middle of the segment
+        let distance = point_to_segment_distance_in_m(&Coordinate::new(0.001, 0.005), &a, &b);
+        assert!((distance - 111.2).abs() < 0.5, "{distance}");
+
+        // Beyond the end of the segment the closest point is the end itself
+        let point = Coordinate::new(0.0, 0.02);
+        let distance = point_to_segment_distance_in_m(&point, &a, &b);
+        assert!(
+            (distance - haversine_distance_in_m(&point, &b)).abs() < 0.5,
+            "{distance}"
+        );
+    }
+}
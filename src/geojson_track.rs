@@ -0,0 +1,127 @@
+use std::{io::Read, path::Path};
+
+use geojson::{Feature, GeoJson, Geometry, JsonObject, LineStringType, Value};
+
+use crate::{
+    error::{Error, Result},
+    model::{ActivityType, Coordinate, TrackHeader, TrackPoint},
+    parser::parse_time,
+};
+
+/// Content of a .geojson track that is used to filter it
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct GeoJsonTrack {
+    /// Properties of the first feature with a line, they also give the name, `time` and `type` of
+    /// the track
+    pub properties: Option<JsonObject>,
+    pub points: Vec<TrackPoint>,
+}
+
+impl GeoJsonTrack {
+    pub fn header(&self) -> TrackHeader {
+        let property = |name: &str| {
+            self.properties
+                .as_ref()
+                .and_then(|properties| properties.get(name))
+                .and_then(|value| value.as_str())
+        };
+        TrackHeader {
+            start_time: property("time").and_then(parse_time),
+            name: property("name").map(String::from),
+            activity_type: property("type").map(ActivityType::parse),
+        }
+    }
+}
+
+/// Read the points of all the `LineString` and `MultiLineString` geometries of the file, which may
+/// contain a single geometry, a feature or a feature collection. Every line string is a line of
+/// its own. Positions are ordered as `[lon, lat, alt]`, other geometries are ignored
+pub fn read_geojson_track<R: Read>(source: R, path: &Path) -> Result<GeoJsonTrack> {
+    let geojson = GeoJson::from_reader(source).map_err(|err| Error::GeoJson {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })?;
+    let features = match geojson {
+        GeoJson::FeatureCollection(collection) => collection.features,
+        GeoJson::Feature(feature) => vec![feature],
+        GeoJson::Geometry(geometry) => vec![Feature::from(geometry)],
+    };
+
+    let mut track = GeoJsonTrack::default();
+    for feature in features {
+        let Some(geometry) = feature.geometry.as_ref() else {
+            continue;
+        };
+        let count = track.points.len();
+        add_line_points(geometry, &mut track.points);
+        if track.properties.is_none() && track.points.len() > count {
+            track.properties = Some(feature.properties.unwrap_or_default());
+        }
+    }
+    Ok(track)
+}
+
+fn add_line_points(geometry: &Geometry, points: &mut Vec<TrackPoint>) {
+    let lines: Vec<&LineStringType> = match &geometry.value {
+        Value::LineString(line) => vec![line],
+        Value::MultiLineString(lines) => lines.iter().collect(),
+        Value::GeometryCollection(geometries) => {
+            for geometry in geometries {
+                add_line_points(geometry, points);
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    for positions in lines {
+        // The line goes after the ones of the previous geometries
+        let line = points.last().map_or(0, |point| point.line + 1);
+        points.extend(positions.iter().filter_map(|position| match position[..] {
+            [longitude, latitude, ref altitude @ ..] => Some(TrackPoint {
+                elevation: altitude.first().copied(),
+                line,
+                ..TrackPoint::new(Coordinate::new(latitude as f32, longitude as f32), None)
+            }),
+            _ => None,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FILE: &str = "test/files/formats/ride.geojson";
+
+    #[test]
+    fn read_multi_line_string() {
+        let geojson = std::fs::read(TEST_FILE).unwrap();
+        let track = read_geojson_track(&geojson[..], Path::new(TEST_FILE)).unwrap();
+
+        assert_eq!(4, track.points.len());
+        let lines: Vec<usize> = track.points.iter().map(|point| point.line).collect();
+        assert_eq!(vec![0, 0, 1, 1], lines);
+        assert_eq!(
+            Coordinate::new(49.479887, 10.985473),
+            track.points[0].coordinate
+        );
+        assert_eq!(Some(310.4), track.points[0].elevation);
+        assert_eq!(None, track.points[1].elevation);
+
+        let properties = track.properties.as_ref().unwrap();
+        assert_eq!(Some(7), properties["source"].as_i64());
+        let header = track.header();
+        assert_eq!(Some(String::from("Derived ride")), header.name);
+        assert_eq!(Some(ActivityType::Ride), header.activity_type);
+        assert_eq!(None, header.start_time);
+    }
+
+    #[test]
+    fn read_invalid_geojson() {
+        assert!(matches!(
+            read_geojson_track(&b"{\"type\": \"LineString\"}"[..], Path::new("a.geojson")),
+            Err(Error::GeoJson { .. })
+        ));
+    }
+}
@@ -1,95 +1,851 @@
 use std::{
+    collections::{HashMap, HashSet},
     fs::File,
     fs::{self, read_dir},
-    io::BufReader,
-    path::PathBuf,
+    io::{self, BufRead, BufReader, Cursor, ErrorKind, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
 };
 
-use quick_xml::{events::Event, reader::Reader};
+use bzip2::read::BzDecoder;
+use flate2::read::MultiGzDecoder;
+use geojson::JsonObject;
+use quick_xml::{
+    events::{BytesStart, Event},
+    reader::Reader,
+};
 
-use crate::model::Coordinate;
+use crate::{
+    archive::{
+        is_archive, list_archive_entries, open_archive_entry, read_kmz_document, split_archive_path,
+    },
+    error::{xml_error, Error, Result},
+    fit::read_fit,
+    format::{Compression, TrackFormat},
+    geojson_track::read_geojson_track,
+    kml::{read_kml_track_header, read_kml_track_points},
+    model::{
+        ActivityType, Coordinate, FolderScan, Gpx, PointKind, SymlinkPolicy, TrackHeader,
+        TrackPoint,
+    },
+    parser::{attribute, parse_gpx, parse_time},
+    tcx::{read_tcx_track_header, read_tcx_track_points},
+};
 
-pub fn read_files_in_folder(directory: &str) -> Vec<PathBuf> {
-    println!("Reading files from '{directory}'...");
+/// Read the .gpx, .fit, .tcx, .kml/.kmz and .geojson files of all the folders, entering up to
+/// `max_depth` levels of subfolders. The files may be compressed with gzip, bzip2 or zstd, and the
+/// folders may be .zip archives or contain them. Only an unreadable top folder is an error, the
+/// files, subfolders and archives that can not be read are skipped and added to `errors`
+pub fn read_files_in_folders(
+    scan: &FolderScan,
+    errors: &Mutex<Vec<Error>>,
+) -> Result<Vec<PathBuf>> {
+    let mut found_files: Vec<PathBuf> = Vec::new();
+    let mut skipped: Vec<Error> = Vec::new();
+    for folder in &scan.folders {
+        eprintln!("Reading files from '{}'...", folder.display());
 
-    let files = read_dir(directory).expect("Directory could not be opened!");
+        // Folders from the top one to the current one, only kept when following the links
+        let mut visited: HashSet<PathBuf> = HashSet::new();
+        if scan.symlinks == SymlinkPolicy::Follow {
+            visited.extend(fs::canonicalize(folder).ok());
+        }
+        match is_archive(folder) && folder.is_file() {
+            true => read_files_in_archive(scan, folder, folder, &mut found_files)?,
+            false => read_files_in_folder(
+                scan,
+                folder,
+                folder,
+                0,
+                &mut visited,
+                &mut found_files,
+                &mut skipped,
+            )?,
+        }
+    }
+    errors.lock().unwrap().append(&mut skipped);
+
+    // The same file is found twice when the folders overlap
+    found_files.sort();
+    found_files.dedup();
+    Ok(found_files)
+}
+
+fn read_files_in_folder(
+    scan: &FolderScan,
+    root: &Path,
+    directory: &Path,
+    depth: usize,
+    visited: &mut HashSet<PathBuf>,
+    found_files: &mut Vec<PathBuf>,
+    errors: &mut Vec<Error>,
+) -> Result<()> {
+    let files = read_dir(directory).map_err(|err| Error::io(directory, err))?;
 
-    let mut found_files: Vec<PathBuf> = Vec::new();
     for file in files {
-        match file {
-            Ok(file) if file.path().extension().unwrap() == "gpx" => {
-                let file_path = file.path();
-                found_files.push(file_path);
-            }
-            Ok(file) => {
-                println!("File {:?} is not a .gpx track", file)
+        let file = match file {
+            Ok(file) => file,
+            Err(err) => {
+                errors.push(Error::io(directory, err));
+                continue;
             }
+        };
+        let path = file.path();
+        let relative_path = path.strip_prefix(root).unwrap_or(&path);
+
+        let is_symlink = file
+            .file_type()
+            .is_ok_and(|file_type| file_type.is_symlink());
+        if is_symlink && scan.symlinks == SymlinkPolicy::Ignore {
+            continue;
+        }
+        // Follows the link, so a link to a folder is a folder
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
             Err(err) => {
-                println!("Error reading file: {err}");
+                errors.push(Error::io(&path, err));
+                continue;
+            }
+        };
+
+        if metadata.is_dir() {
+            let can_enter = !is_symlink || scan.symlinks == SymlinkPolicy::Follow;
+            if depth >= scan.max_depth || !can_enter || scan.is_excluded(relative_path) {
+                continue;
             }
+            // A link to one of the folders above would never end
+            let canonical = match scan.symlinks {
+                SymlinkPolicy::Follow => match fs::canonicalize(&path) {
+                    Ok(canonical) if !visited.contains(&canonical) => Some(canonical),
+                    _ => continue,
+                },
+                _ => None,
+            };
+            visited.extend(canonical.clone());
+            if let Err(err) =
+                read_files_in_folder(scan, root, &path, depth + 1, visited, found_files, errors)
+            {
+                errors.push(err);
+            }
+            if let Some(canonical) = canonical {
+                visited.remove(&canonical);
+            }
+        } else if is_archive(&path) {
+            if let Err(err) = read_files_in_archive(scan, root, &path, found_files) {
+                errors.push(err);
+            }
+        } else if !is_track_file(&path) {
+            eprintln!("File {:?} is not a track", path);
+        } else if scan.is_selected(relative_path) {
+            found_files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Add the tracks in the archive, whose paths are the path of the archive followed by the name of
+/// the entry
+fn read_files_in_archive(
+    scan: &FolderScan,
+    root: &Path,
+    archive: &Path,
+    found_files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    for path in list_archive_entries(archive)? {
+        let relative_path = path.strip_prefix(root).unwrap_or(&path);
+        if is_track_file(&path) && scan.is_selected(relative_path) {
+            found_files.push(path);
         }
     }
-    found_files
+    Ok(())
+}
+
+/// Check the extension below the one of the compression, without panicking on the files that
+/// have none
+fn is_track_file(path: &Path) -> bool {
+    TrackFormat::of(path).is_some()
+}
+
+/// Read the coordinates of the points of the given kinds of the track, a .gpx, .fit, .tcx,
+/// .kml/.kmz or .geojson file. The points of the formats other than .gpx are all track points
+pub fn read_track_coordinates(path: &Path, kinds: &[PointKind]) -> Result<Vec<Coordinate>> {
+    let points = read_track_points(path)?;
+    Ok(points
+        .into_iter()
+        .filter(|point| kinds.contains(&point.kind))
+        .map(|point| point.coordinate)
+        .collect())
 }
 
-pub fn read_xml_file(path: &PathBuf) -> Vec<Coordinate> {
-    let file = File::open(path).unwrap();
-    let buff_reader = BufReader::new(file);
+/// Open the file, or the entry of an archive, for buffered reading. Compressed files are
+/// decompressed while they are read, of a .kmz file only its KML document is read
+pub fn open_track(path: &Path) -> Result<Box<dyn BufRead>> {
+    let raw: Box<dyn Read> = match split_archive_path(path) {
+        Some((archive, name)) => open_archive_entry(archive, &name)?,
+        None => Box::new(File::open(path).map_err(|err| Error::io(path, err))?),
+    };
+
+    let reader: Box<dyn Read> = match Compression::of(path) {
+        Compression::None => raw,
+        Compression::Gzip => Box::new(MultiGzDecoder::new(raw)),
+        Compression::Bzip2 => Box::new(BzDecoder::new(raw)),
+        Compression::Zstd => Box::new(zstd::Decoder::new(raw).map_err(|err| Error::io(path, err))?),
+    };
+    if TrackFormat::of(path) == Some(TrackFormat::Kmz) {
+        return Ok(Box::new(Cursor::new(read_kmz_document(reader, path)?)));
+    }
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+/// Read the track points of the file, a .gpx, .fit, .tcx, .kml/.kmz or .geojson one, with their
+/// time and elevation
+pub fn read_track_points(path: &Path) -> Result<Vec<TrackPoint>> {
+    Ok(read_track(path)?.0)
+}
 
-    let mut reader = Reader::from_reader(Box::new(buff_reader));
+/// Read the track points of the file together with the properties of the feature of a .geojson
+/// track, so the file is parsed only once. The other formats have no properties
+pub fn read_track(path: &Path) -> Result<(Vec<TrackPoint>, Option<JsonObject>)> {
+    let source = open_track(path)?;
+    let points = match TrackFormat::of(path) {
+        Some(TrackFormat::Fit) => read_fit(source, path)?.points,
+        Some(TrackFormat::Tcx) => read_tcx_track_points(source, path)?,
+        Some(TrackFormat::Kml | TrackFormat::Kmz) => read_kml_track_points(source, path)?,
+        Some(TrackFormat::GeoJson) => {
+            let track = read_geojson_track(source, path)?;
+            return Ok((track.points, track.properties));
+        }
+        _ => read_gpx_track_points(source, path)?,
+    };
+    Ok((points, None))
+}
+
+/// Read the waypoints, route points and track points of the .gpx file, in this order, with their
+/// kind, time and elevation. Every waypoint, route and track segment is a line of its own. The
+/// points are read as the file is streamed, the rest of its content is not kept
+fn read_gpx_track_points<R: BufRead>(source: R, path: &Path) -> Result<Vec<TrackPoint>> {
+    let mut reader = Reader::from_reader(source);
     reader.trim_text(true);
 
     let mut buf = Vec::new();
-    let mut coordinates: Vec<Coordinate> = Vec::new();
-    // The `Reader` does not implement `Iterator` because it outputs borrowed data (`Cow`s)
+    let mut points = GpxPoints::default();
     loop {
-        // NOTE: this is the generic case when we don't know about the input BufRead.
-        // when the input is a &str or a &[u8], we don't actually need to use another
-        // buffer, we could directly call `reader.read_event()`
         match reader.read_event_into(&mut buf) {
-            Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
+            Err(e) => return Err(xml_error(path, &reader, e)),
             Ok(Event::Eof) => break,
-            Ok(Event::Start(e)) => match e.name().as_ref() {
-                b"trkpt" => {
-                    let longitude: Option<f32> = e
-                        .attributes()
-                        .filter_map(|a| a.ok())
-                        .find(|attr| attr.key == quick_xml::name::QName(b"lon"))
-                        .and_then(|attr| String::from_utf8_lossy(&attr.value).parse().ok());
-                    let latitude: Option<f32> = e
-                        .attributes()
-                        .filter_map(|a| a.ok())
-                        .find(|attr| attr.key == quick_xml::name::QName(b"lat"))
-                        .and_then(|attr| String::from_utf8_lossy(&attr.value).parse().ok());
-                    if latitude.is_some() && longitude.is_some() {
-                        coordinates.push(Coordinate::new(latitude.unwrap(), longitude.unwrap()))
-                    }
+            Ok(Event::Start(e)) => points.start(&e),
+            Ok(Event::Empty(e)) => {
+                points.start(&e);
+                points.end(e.local_name().as_ref());
+            }
+            Ok(Event::End(e)) => points.end(e.local_name().as_ref()),
+            Ok(Event::Text(e)) => {
+                if let Ok(text) = e.unescape() {
+                    points.text(&text);
                 }
-                _ => (),
-            },
+            }
+            Ok(Event::CData(e)) => points.text(&String::from_utf8_lossy(&e)),
             _ => (), // ignore other xml events
         }
         buf.clear(); // clear memory
     }
-    coordinates
+    Ok(points.into_points())
+}
+
+/// Points of a .gpx file found so far, grouped by kind as `parse_gpx` does
+#[derive(Default)]
+struct GpxPoints {
+    waypoints: Vec<TrackPoint>,
+    /// Route and track points have the index of their route or segment as line
+    route_points: Vec<TrackPoint>,
+    track_points: Vec<TrackPoint>,
+    routes: usize,
+    tracks: usize,
+    segments: usize,
+    /// Point whose element is open, if it has a valid `lat` and `lon`
+    point: Option<TrackPoint>,
+    /// Open `<extensions>` elements, whose `<ele>` and `<time>` are not the ones of the point
+    extensions: usize,
+    in_elevation: bool,
+    in_time: bool,
+}
+
+impl GpxPoints {
+    fn start(&mut self, element: &BytesStart) {
+        let in_point = self.point.is_some() && self.extensions == 0;
+        self.in_elevation = false;
+        self.in_time = false;
+        match element.local_name().as_ref() {
+            b"rte" => self.routes += 1,
+            b"trk" => self.tracks += 1,
+            b"trkseg" if self.tracks > 0 => self.segments += 1,
+            b"extensions" => self.extensions += 1,
+            b"ele" => self.in_elevation = in_point,
+            b"time" => self.in_time = in_point,
+            name => {
+                if let Some(kind) = PointKind::of_element(name) {
+                    self.point = read_point(element, kind);
+                }
+            }
+        }
+    }
+
+    fn end(&mut self, name: &[u8]) {
+        self.in_elevation = false;
+        self.in_time = false;
+        let (points, line) = match name {
+            b"extensions" => {
+                self.extensions = self.extensions.saturating_sub(1);
+                return;
+            }
+            b"wpt" => (&mut self.waypoints, 0),
+            b"rtept" if self.routes > 0 => (&mut self.route_points, self.routes - 1),
+            b"trkpt" if self.segments > 0 => (&mut self.track_points, self.segments - 1),
+            b"rtept" | b"trkpt" => {
+                self.point = None;
+                return;
+            }
+            _ => return,
+        };
+        if let Some(point) = self.point.take() {
+            points.push(TrackPoint { line, ..point });
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let Some(point) = self.point.as_mut() else {
+            return;
+        };
+        if self.in_elevation {
+            point.elevation = text.trim().parse().ok();
+        } else if self.in_time {
+            point.time = parse_time(text);
+        }
+    }
+
+    /// Waypoints first, then the routes and the track segments, numbering their lines in this order
+    fn into_points(self) -> Vec<TrackPoint> {
+        let mut points = self.waypoints;
+        for (line, point) in points.iter_mut().enumerate() {
+            point.line = line;
+        }
+        let routes_line = points.len();
+        let segments_line = routes_line + self.routes;
+        points.extend(self.route_points.into_iter().map(|point| TrackPoint {
+            line: routes_line + point.line,
+            ..point
+        }));
+        points.extend(self.track_points.into_iter().map(|point| TrackPoint {
+            line: segments_line + point.line,
+            ..point
+        }));
+        points
+    }
+}
+
+/// Point of a `<trkpt>`, `<rtept>` or `<wpt>` element, if it has a valid `lat` and `lon`
+fn read_point(element: &BytesStart, kind: PointKind) -> Option<TrackPoint> {
+    let latitude: f32 = attribute(element, b"lat")?.parse().ok()?;
+    let longitude: f32 = attribute(element, b"lon")?.parse().ok()?;
+    Some(TrackPoint {
+        kind,
+        ..TrackPoint::new(Coordinate::new(latitude, longitude), None)
+    })
+}
+
+/// Read the start time, name and type of the track. The file is only read until the end of the
+/// first point, which has the start time when there is no `<metadata>`
+pub fn read_track_header(path: &Path) -> Result<TrackHeader> {
+    let source = open_track(path)?;
+    match TrackFormat::of(path) {
+        Some(TrackFormat::Fit) => {
+            // FIT files have no name, and the sport is usually at the end of the file
+            let activity = read_fit(source, path)?;
+            Ok(TrackHeader {
+                start_time: activity.start_time,
+                name: None,
+                activity_type: activity.activity_type,
+            })
+        }
+        Some(TrackFormat::Tcx) => read_tcx_track_header(source, path),
+        Some(TrackFormat::Kml | TrackFormat::Kmz) => read_kml_track_header(source, path),
+        Some(TrackFormat::GeoJson) => Ok(read_geojson_track(source, path)?.header()),
+        _ => read_gpx_track_header(source, path),
+    }
 }
 
-/// Copy the provided file in the provided folder with the same name
-pub fn copy_gpx_file(output: &str, file: PathBuf) {
-    let filename = file.file_name().unwrap().to_str().unwrap();
-    let output = String::from(output) + "/" + filename;
-    fs::copy(file, output).unwrap();
+fn read_gpx_track_header<R: BufRead>(source: R, path: &Path) -> Result<TrackHeader> {
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut header = TrackHeader::default();
+    let mut metadata_name: Option<String> = None;
+    // Local names of the elements that are currently open
+    let mut open_elements: Vec<Vec<u8>> = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Err(e) => return Err(xml_error(path, &reader, e)),
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => open_elements.push(e.local_name().as_ref().to_vec()),
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"trkpt" => break,
+            // A self-closing first point has no time, the rest of the file is not needed either
+            Ok(Event::Empty(e)) if e.local_name().as_ref() == b"trkpt" => break,
+            Ok(Event::End(_)) => {
+                open_elements.pop();
+            }
+            Ok(Event::Text(e)) => {
+                let [.., parent, element] = &open_elements[..] else {
+                    continue;
+                };
+                let Ok(text) = e.unescape() else {
+                    continue;
+                };
+                match (parent.as_slice(), element.as_slice()) {
+                    (b"metadata", b"time") | (b"trkpt", b"time") if header.start_time.is_none() => {
+                        header.start_time = parse_time(&text)
+                    }
+                    (b"metadata", b"name") => metadata_name = Some(text.into_owned()),
+                    (b"trk", b"name") => header.name = Some(text.into_owned()),
+                    (b"trk", b"type") => header.activity_type = Some(ActivityType::parse(&text)),
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    header.name = header.name.or(metadata_name);
+    Ok(header)
+}
+
+/// Read the whole content of the .gpx file, including metadata, routes and waypoints
+pub fn read_gpx_file(path: &Path) -> Result<Gpx> {
+    parse_gpx(open_track(path)?, path)
+}
+
+/// Path of the file relative to the scanned folder it was found in, or only its name when it is
+/// in none of them
+pub fn relative_path<'a>(file: &'a Path, folders: &[PathBuf]) -> &'a Path {
+    folders
+        .iter()
+        .find_map(|folder| file.strip_prefix(folder).ok())
+        .filter(|relative| relative.file_name().is_some())
+        .or_else(|| file.file_name().map(Path::new))
+        .unwrap_or(file)
+}
+
+/// Copy the file to the target path, creating its folders if needed. The entries of an archive are
+/// extracted as they are, without decompressing them. A target already in `copied` is not
+/// replaced, so two files that would be copied to the same path are reported instead
+pub fn copy_gpx_file(file: &Path, target: PathBuf, copied: &mut HashSet<PathBuf>) -> Result<()> {
+    if copied.contains(&target) {
+        let message = format!("{} was already copied from another file", target.display());
+        return Err(Error::io(
+            file,
+            io::Error::new(ErrorKind::AlreadyExists, message),
+        ));
+    }
+    if let Some(folder) = target.parent() {
+        fs::create_dir_all(folder).map_err(|err| Error::io(folder, err))?;
+    }
+
+    match split_archive_path(file) {
+        Some((archive, name)) => {
+            let mut entry = open_archive_entry(archive, &name)?;
+            let mut copy = File::create(&target).map_err(|err| Error::io(&target, err))?;
+            io::copy(&mut entry, &mut copy).map_err(|err| Error::io(&target, err))?;
+        }
+        None => {
+            fs::copy(file, &target).map_err(|err| Error::io(&target, err))?;
+        }
+    }
+    copied.insert(target);
+    Ok(())
+}
+
+/// Create, if needed, the subfolder of the output folder where the files of an area are copied.
+/// Characters that are not allowed in folder names are replaced by `_`. The names that would not
+/// make a folder of their own, like `..`, are rejected, and so is an area whose folder was already
+/// created for another area, which `created` keeps track of
+pub fn create_area_folder(
+    output: &Path,
+    area: &str,
+    created: &mut HashMap<PathBuf, String>,
+) -> Result<PathBuf> {
+    let name: String = area
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim();
+    let folder = output.join(name);
+    let invalid =
+        |message: String| Error::io(&folder, io::Error::new(ErrorKind::InvalidInput, message));
+
+    if matches!(name, "" | "." | "..") {
+        return Err(invalid(format!("area '{area}' can not name a folder")));
+    }
+    match created.get(&folder) {
+        Some(other) if other != area => {
+            return Err(invalid(format!(
+                "areas '{other}' and '{area}' share the folder"
+            )));
+        }
+        Some(_) => return Ok(folder),
+        None => (),
+    }
+    fs::create_dir_all(&folder).map_err(|err| Error::io(&folder, err))?;
+    created.insert(folder.clone(), String::from(area));
+    Ok(folder)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::slice;
+
+    use glob::Pattern;
+
     use super::*;
 
     const TEST_FILE: &str = "test/files/9244476879.gpx";
+
+    /// Folder of one test in the temporary directory, removed when it is dropped
+    struct TestDir {
+        path: PathBuf,
+    }
+
+    impl TestDir {
+        fn new(test: &str) -> TestDir {
+            let name = format!("gpx-geo-filter-{test}-{}", std::process::id());
+            let path = std::env::temp_dir().join(name);
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TestDir { path }
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
     #[test]
-    fn test_read_xml() {
+    fn test_read_track_coordinates() {
         let path = PathBuf::from(TEST_FILE);
-        let coordinates = read_xml_file(&path);
+        let coordinates = read_track_coordinates(&path, &[PointKind::Track]).unwrap();
         assert_eq!(2245, coordinates.len());
+        let waypoints = read_track_coordinates(&path, &[PointKind::Waypoint]).unwrap();
+        assert!(waypoints.is_empty());
+    }
+
+    #[test]
+    fn test_read_track_points_with_time() {
+        let path = PathBuf::from(TEST_FILE);
+        let points = read_track_points(&path).unwrap();
+
+        assert_eq!(2245, points.len());
+        assert_eq!(
+            "2023-02-23T19:34:16+00:00",
+            points[0].time.unwrap().to_rfc3339()
+        );
+        assert!(points.iter().all(|point| point.time.is_some()));
+    }
+
+    #[test]
+    fn test_streamed_points_match_the_parsed_gpx() {
+        let path = PathBuf::from(TEST_FILE);
+        let points = read_track_points(&path).unwrap();
+        let gpx = read_gpx_file(&path).unwrap();
+
+        assert!(gpx
+            .track_points()
+            .map(TrackPoint::from)
+            .eq(points.iter().map(|point| TrackPoint { line: 0, ..*point })));
+
+        // The time of an extension is not the time of the point
+        let input = r#"<gpx><trk><trkseg><trkpt lat="49.47" lon="11.09">
+ <extensions><time>2023-03-03T08:43:59Z</time></extensions><ele>310</ele>
+</trkpt></trkseg></trk></gpx>"#;
+        let points = read_gpx_track_points(input.as_bytes(), Path::new("a.gpx")).unwrap();
+        assert_eq!((None, Some(310.0)), (points[0].time, points[0].elevation));
+    }
+
+    #[test]
+    fn test_read_route_and_waypoints() {
+        let path = PathBuf::from("test/files/formats/plan.gpx");
+        let points = read_track_points(&path).unwrap();
+
+        let kinds: Vec<(PointKind, usize)> = points
+            .iter()
+            .map(|point| (point.kind, point.line))
+            .collect();
+        assert_eq!(
+            vec![
+                (PointKind::Waypoint, 0),
+                (PointKind::Route, 1),
+                (PointKind::Route, 1),
+                (PointKind::Track, 2),
+                (PointKind::Track, 2)
+            ],
+            kinds
+        );
+        assert_eq!(Some(310.0), points[0].elevation);
+        assert_eq!(Coordinate::new(49.50, 11.08), points[2].coordinate);
+        assert!(points[4].time.is_some());
+    }
+
+    #[test]
+    fn test_read_track_header() {
+        let header = read_track_header(&PathBuf::from("test/files/8651674449.gpx")).unwrap();
+        assert_eq!(
+            "2023-03-03T08:43:59+00:00",
+            header.start_time.unwrap().to_rfc3339()
+        );
+        assert_eq!(Some(String::from("Morning Walk")), header.name);
+        assert_eq!(Some(ActivityType::Walk), header.activity_type);
+
+        let header = read_track_header(&PathBuf::from(TEST_FILE)).unwrap();
+        assert_eq!(
+            "2023-02-23T19:34:16+00:00",
+            header.start_time.unwrap().to_rfc3339()
+        );
+        assert_eq!(None, header.name);
+
+        // Only the first point is read, even when it is self-closing
+        let input = r#"<gpx><trk><name>Plan</name><trkseg>
+  <trkpt lat="49.47" lon="11.09"/>
+  <trkpt lat="49.48" lon="11.10"><time>2023-03-03T08:43:59Z</time></trkpt>
+ </trkseg></trk></gpx>"#;
+        let header = read_gpx_track_header(input.as_bytes(), Path::new("plan.gpx")).unwrap();
+        assert_eq!(Some(String::from("Plan")), header.name);
+        assert_eq!(None, header.start_time);
+    }
+
+    #[test]
+    fn test_read_gpx_file() {
+        let path = PathBuf::from(TEST_FILE);
+        let gpx = read_gpx_file(&path).unwrap();
+
+        assert_eq!(1, gpx.tracks.len());
+        assert_eq!(2245, gpx.track_points().count());
+        let points = read_track_points(&path).unwrap();
+        assert!(gpx
+            .track_points()
+            .zip(&points)
+            .all(|(point, other)| &TrackPoint::from(point) == other));
+        assert!(gpx.track_points().all(|point| point.elevation.is_some()));
+    }
+
+    #[test]
+    fn test_errors_instead_of_panics() {
+        let missing = PathBuf::from("test/files/missing.gpx");
+        assert!(matches!(
+            read_track_points(&missing),
+            Err(Error::Io { path, .. }) if path == missing
+        ));
+        assert!(
+            read_files_in_folders(&FolderScan::new("test/missing"), &Mutex::default()).is_err()
+        );
+
+        let dir = TestDir::new("test_errors_instead_of_panics");
+        let corrupt = dir.path.join("corrupt.gpx");
+        fs::write(
+            &corrupt,
+            "<gpx><trk><trkseg><trkpt lat=\"1\" lon=\"2\"></trk></gpx>",
+        )
+        .unwrap();
+        assert!(matches!(
+            read_track_points(&corrupt),
+            Err(Error::Xml { .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_files_in_subfolders() {
+        let dir = TestDir::new("test_read_files_in_subfolders");
+        let root = dir.path.clone();
+        for folder in ["2023/03", "2023/04", "2024/01"] {
+            fs::create_dir_all(root.join(folder)).unwrap();
+        }
+        for file in [
+            "top.gpx",
+            "README",
+            "2023/03/a.gpx",
+            "2023/04/b.GPX",
+            "2024/01/c.gpx",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let names = |scan: &FolderScan| -> Vec<String> {
+            read_files_in_folders(scan, &Mutex::default())
+                .unwrap()
+                .iter()
+                .map(|path| path.strip_prefix(&root).unwrap().display().to_string())
+                .collect()
+        };
+
+        let mut scan = FolderScan::new(&root);
+        assert_eq!(vec!["top.gpx"], names(&scan));
+
+        scan.max_depth = 1;
+        assert_eq!(vec!["top.gpx"], names(&scan));
+
+        scan.max_depth = usize::MAX;
+        assert_eq!(
+            vec!["2023/03/a.gpx", "2023/04/b.GPX", "2024/01/c.gpx", "top.gpx"],
+            names(&scan)
+        );
+
+        scan.include = vec![Pattern::new("2023/*").unwrap()];
+        scan.exclude = vec![Pattern::new("*/04").unwrap()];
+        assert_eq!(vec!["2023/03/a.gpx"], names(&scan));
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("2024"), root.join("2023/link")).unwrap();
+            scan.include = vec![Pattern::new("2023/**").unwrap()];
+            scan.exclude.clear();
+            assert_eq!(vec!["2023/03/a.gpx", "2023/04/b.GPX"], names(&scan));
+
+            scan.symlinks = SymlinkPolicy::Follow;
+            assert_eq!(
+                vec!["2023/03/a.gpx", "2023/04/b.GPX", "2023/link/01/c.gpx"],
+                names(&scan)
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_compressed_files_and_archives() {
+        use std::io::Write;
+
+        let dir = TestDir::new("test_read_compressed_files_and_archives");
+        let root = dir.path.clone();
+        let content = fs::read(TEST_FILE).unwrap();
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&content).unwrap();
+        fs::write(root.join("a.gpx.gz"), gzip.finish().unwrap()).unwrap();
+        let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bzip2.write_all(&content).unwrap();
+        fs::write(root.join("b.gpx.bz2"), bzip2.finish().unwrap()).unwrap();
+        fs::write(
+            root.join("c.gpx.zst"),
+            zstd::encode_all(&content[..], 0).unwrap(),
+        )
+        .unwrap();
+        fs::write(root.join("notes.txt.gz"), "").unwrap();
+
+        let mut zip = zip::ZipWriter::new(File::create(root.join("export.zip")).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("activities/", options).unwrap();
+        zip.start_file("activities/d.gpx", options).unwrap();
+        zip.write_all(&content).unwrap();
+        // Compressed files are usually stored as they are, the others are deflated
+        let stored = options.compression_method(zip::CompressionMethod::Stored);
+        zip.start_file("activities/e.gpx.gz", stored).unwrap();
+        zip.write_all(&fs::read(root.join("a.gpx.gz")).unwrap())
+            .unwrap();
+        zip.start_file("activities.csv", options).unwrap();
+        zip.finish().unwrap();
+        fs::write(root.join("broken.zip"), "").unwrap();
+
+        let errors = Mutex::default();
+        let files = read_files_in_folders(&FolderScan::new(&root), &errors).unwrap();
+        let names: Vec<String> = files
+            .iter()
+            .map(|path| path.strip_prefix(&root).unwrap().display().to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "a.gpx.gz",
+                "b.gpx.bz2",
+                "c.gpx.zst",
+                "export.zip/activities/d.gpx",
+                "export.zip/activities/e.gpx.gz"
+            ],
+            names
+        );
+        // The broken archive is skipped, but not silently
+        let errors = errors.into_inner().unwrap();
+        assert!(
+            matches!(&errors[..], [Error::Archive { path, .. }] if path.ends_with("broken.zip"))
+        );
+        for path in &files {
+            assert_eq!(2245, read_track_points(path).unwrap().len());
+        }
+        assert!(matches!(
+            read_track_points(&root.join("export.zip/activities/f.gpx")),
+            Err(Error::Archive { .. })
+        ));
+
+        // The archive itself can be the folder
+        let scan = FolderScan::new(root.join("export.zip"));
+        let files = read_files_in_folders(&scan, &Mutex::default()).unwrap();
+        assert_eq!(2, files.len());
+
+        let output = root.join("output");
+        let mut copied = HashSet::new();
+        let relative = relative_path(&files[1], &[root.join("export.zip")]);
+        assert_eq!(Path::new("activities/e.gpx.gz"), relative);
+        copy_gpx_file(&files[1], output.join(relative), &mut copied).unwrap();
+        assert_eq!(
+            fs::read(root.join("a.gpx.gz")).unwrap(),
+            fs::read(output.join("activities/e.gpx.gz")).unwrap()
+        );
+
+        // The same name in another folder is not copied over it
+        let same_name = root.join("activities/e.gpx.gz");
+        assert_eq!(relative, relative_path(&same_name, slice::from_ref(&root)));
+        assert!(copy_gpx_file(&same_name, output.join(relative), &mut copied).is_err());
+    }
+
+    #[test]
+    fn test_read_kml_and_kmz_files() {
+        let scan = FolderScan::new("test/files/formats");
+        let files = read_files_in_folders(&scan, &Mutex::default()).unwrap();
+        for path in [
+            "test/files/formats/rides.kml",
+            "test/files/formats/rides.kmz",
+        ] {
+            let path = PathBuf::from(path);
+            assert!(files.contains(&path));
+            assert_eq!(4, read_track_points(&path).unwrap().len());
+            let header = read_track_header(&path).unwrap();
+            assert_eq!(Some(String::from("Planned")), header.name);
+        }
+
+        let dir = TestDir::new("test_read_kml_and_kmz_files");
+        let empty = dir.path.join("empty.kmz");
+        fs::write(&empty, []).unwrap();
+        assert!(matches!(
+            read_track_points(&empty),
+            Err(Error::Archive { .. })
+        ));
+    }
+
+    #[test]
+    fn test_create_area_folder() {
+        let dir = TestDir::new("test_create_area_folder");
+        let output = dir.path.join("output");
+        let mut created = HashMap::new();
+        let folder = create_area_folder(&output, "North/South: 1", &mut created).unwrap();
+
+        assert!(folder.ends_with("North_South_ 1"));
+        assert!(folder.is_dir());
+        assert_eq!(
+            folder,
+            create_area_folder(&output, "North/South: 1", &mut created).unwrap()
+        );
+        assert!(create_area_folder(&output, "North:South: 1", &mut created).is_err());
+        for area in ["", " ", ".", ".."] {
+            assert!(create_area_folder(&output, area, &mut created).is_err());
+        }
     }
 }
@@ -0,0 +1,188 @@
+use std::{io::BufRead, path::Path};
+
+use chrono::{DateTime, Utc};
+use quick_xml::{events::Event, reader::Reader};
+
+use crate::{
+    error::{xml_error, Result},
+    model::{Coordinate, TrackHeader, TrackPoint},
+    parser::{local_name, parse_time},
+};
+
+/// Read the points of the `<LineString>` and `<gx:Track>` geometries of a .kml document, each
+/// geometry is a line of its own. The coordinates of KML are ordered as `lon,lat[,alt]`, tuples
+/// that can not be parsed are skipped
+pub fn read_kml_track_points<R: BufRead>(source: R, path: &Path) -> Result<Vec<TrackPoint>> {
+    read_points(source, path, false)
+}
+
+/// Read the .kml document only until the end of the first geometry with points
+pub fn read_first_kml_coordinate<R: BufRead>(source: R, path: &Path) -> Result<Option<Coordinate>> {
+    let points = read_points(source, path, true)?;
+    Ok(points.first().map(|point| point.coordinate))
+}
+
+fn read_points<R: BufRead>(source: R, path: &Path, first_only: bool) -> Result<Vec<TrackPoint>> {
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut points: Vec<TrackPoint> = Vec::new();
+    // Local names of the elements that are currently open
+    let mut open_elements: Vec<String> = Vec::new();
+    // A `<gx:Track>` lists all its `<when>` before or after all its `<gx:coord>`, they are paired
+    // by their order once the track ends
+    let mut whens: Vec<Option<DateTime<Utc>>> = Vec::new();
+    let mut coords: Vec<TrackPoint> = Vec::new();
+    // Index of the geometry that is being read
+    let mut line = 0;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Err(e) => return Err(xml_error(path, &reader, e)),
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => open_elements.push(local_name(&e)),
+            Ok(Event::End(_)) => match open_elements.pop().as_deref() {
+                Some("Track") => {
+                    for (index, mut point) in coords.drain(..).enumerate() {
+                        point.time = whens.get(index).copied().flatten();
+                        point.line = line;
+                        points.push(point);
+                    }
+                    whens.clear();
+                    line += 1;
+                    if first_only && !points.is_empty() {
+                        break;
+                    }
+                }
+                Some("LineString") => {
+                    line += 1;
+                    if first_only && !points.is_empty() {
+                        break;
+                    }
+                }
+                _ => (),
+            },
+            Ok(Event::Text(e)) => {
+                if let ([.., parent, element], Ok(text)) = (&open_elements[..], e.unescape()) {
+                    match (parent.as_str(), element.as_str()) {
+                        ("LineString", "coordinates") => points.extend(
+                            text.split_whitespace()
+                                .filter_map(|tuple| parse_tuple(tuple.split(',')))
+                                .map(|point| TrackPoint { line, ..point }),
+                        ),
+                        ("Track", "when") => whens.push(parse_time(&text)),
+                        ("Track", "coord") => coords.extend(parse_tuple(text.split_whitespace())),
+                        _ => (),
+                    }
+                }
+            }
+            _ => (), // ignore other xml events
+        }
+        buf.clear(); // clear memory
+    }
+    Ok(points)
+}
+
+/// Parse the longitude, latitude and optional altitude of a KML position
+fn parse_tuple<'a>(mut values: impl Iterator<Item = &'a str>) -> Option<TrackPoint> {
+    let longitude: f32 = values.next()?.trim().parse().ok()?;
+    let latitude: f32 = values.next()?.trim().parse().ok()?;
+    Some(TrackPoint {
+        elevation: values
+            .next()
+            .and_then(|altitude| altitude.trim().parse().ok()),
+        ..TrackPoint::new(Coordinate::new(latitude, longitude), None)
+    })
+}
+
+/// Read the name and start time of the track. The name is the one of the `<Placemark>`, or of the
+/// `<Document>` otherwise, and the start time the first `<when>` or `<begin>`. The document is only
+/// read until the end of the first geometry, KML has no activity type
+pub fn read_kml_track_header<R: BufRead>(source: R, path: &Path) -> Result<TrackHeader> {
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut header = TrackHeader::default();
+    let mut document_name: Option<String> = None;
+    let mut open_elements: Vec<String> = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Err(e) => return Err(xml_error(path, &reader, e)),
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => open_elements.push(local_name(&e)),
+            Ok(Event::End(e)) if matches!(e.local_name().as_ref(), b"LineString" | b"Track") => {
+                break
+            }
+            Ok(Event::End(_)) => {
+                open_elements.pop();
+            }
+            Ok(Event::Text(e)) => {
+                if let ([.., parent, element], Ok(text)) = (&open_elements[..], e.unescape()) {
+                    match (parent.as_str(), element.as_str()) {
+                        (_, "when") | ("TimeSpan", "begin") if header.start_time.is_none() => {
+                            header.start_time = parse_time(&text)
+                        }
+                        ("Placemark", "name") => header.name = Some(text.into_owned()),
+                        ("Document", "name") => document_name = Some(text.into_owned()),
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    header.name = header.name.or(document_name);
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FILE: &str = "test/files/formats/rides.kml";
+
+    #[test]
+    fn read_line_string_and_track() {
+        let kml = std::fs::read(TEST_FILE).unwrap();
+        let path = Path::new(TEST_FILE);
+        let points = read_kml_track_points(&kml[..], path).unwrap();
+
+        assert_eq!(4, points.len());
+        let lines: Vec<usize> = points.iter().map(|point| point.line).collect();
+        assert_eq!(vec![0, 0, 1, 1], lines);
+        assert_eq!(Coordinate::new(49.479887, 10.985473), points[0].coordinate);
+        assert_eq!(Some(310.4), points[0].elevation);
+        assert_eq!((None, None), (points[1].elevation, points[1].time));
+        assert_eq!(Coordinate::new(49.4711, 10.9912), points[3].coordinate);
+        assert_eq!(Some(305.0), points[3].elevation);
+        assert_eq!(
+            "2023-03-03T08:54:05+00:00",
+            points[3].time.unwrap().to_rfc3339()
+        );
+
+        let first = read_first_kml_coordinate(&kml[..], path).unwrap();
+        assert_eq!(Some(points[0].coordinate), first);
+    }
+
+    #[test]
+    fn read_header() {
+        let kml = std::fs::read_to_string(TEST_FILE).unwrap();
+        let header = read_kml_track_header(kml.as_bytes(), Path::new(TEST_FILE)).unwrap();
+        assert_eq!(Some(String::from("Planned")), header.name);
+        assert_eq!(None, header.start_time);
+        assert_eq!(None, header.activity_type);
+
+        let track = kml.replace("<name>Planned</name>", "");
+        let track = track.replace("<LineString>", "<Point>");
+        let track = track.replace("</LineString>", "</Point>");
+        let header = read_kml_track_header(track.as_bytes(), Path::new("rides.kml")).unwrap();
+        assert_eq!(Some(String::from("Shared rides")), header.name);
+        assert_eq!(
+            "2023-03-03T08:44:05+00:00",
+            header.start_time.unwrap().to_rfc3339()
+        );
+    }
+}
@@ -1,21 +1,47 @@
-use std::{path::PathBuf, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
 
 use crate::{
-    filter::{filter_tracks_outside_area, prefilter_files},
-    io::{copy_gpx_file, read_files_in_folder},
-    model::{Coordinate, SquaredFilter},
+    error::{Error, Result},
+    filter::{
+        compute_statistics_of_tracks, filter_tracks_by_expression,
+        filter_tracks_mostly_outside_area, filter_tracks_not_following_sequence,
+        filter_tracks_not_meeting_conditions, filter_tracks_outside_area, prefilter_files,
+    },
+    io::{copy_gpx_file, create_area_folder, read_files_in_folders, read_gpx_file, relative_path},
+    model::{
+        AreaExpression, AreaFilter, Coordinate, CorridorFilter, FilterReport, FilterResult,
+        FilterSummary, FolderScan, Gpx, MatchMode, NamedArea, SquaredFilter, TrackConditions,
+    },
 };
 
-pub mod config;
+mod archive;
+pub mod areas;
 pub mod cli;
+pub mod config;
+pub mod error;
 mod filter;
+mod fit;
+mod format;
+mod geodesy;
+mod geojson_track;
 mod io;
-mod model;
+mod kml;
+pub mod model;
+pub mod output;
 mod parser;
+mod statistics;
+mod tcx;
 mod utils;
 
-/// Filter the .gpx tracks found in the provided folder to only return the paths of the elements
+/// Filter the .gpx tracks found in the provided folder to only return the tracks
 /// that are within the area of the provided two points. The first one must be the most south-west.
+/// The tracks whose points were read are returned with their distance, duration, elevation gain
+/// and bounding box. Those matched by their start only have them after `add_missing_statistics`.
 ///
 /// Example:
 ///
@@ -32,14 +58,25 @@ mod utils;
 /// let distance = 300.0;
 /// let threads: usize = 12;
 ///
-/// let files = filter_tracks(DIRECTORY, first_lat, first_lon, second_lat, second_lon, distance, threads);
+/// let report =
+///     filter_tracks(DIRECTORY, first_lat, first_lon, second_lat, second_lon, distance, threads)
+///         .unwrap();
+/// println!("{} of {} files matched", report.summary.files_matched, report.summary.files_found);
+/// for track in report.results {
+///     match track.statistics {
+///         Some(statistics) => println!("{:?}: {}", track.path, statistics),
+///         None => println!("{:?}", track.path),
+///     }
+/// }
+/// for error in report.errors {
+///     println!("Skipped {error}");
+/// }
 /// ```
 ///
-/// # Panics
+/// # Errors
 ///
-/// Panics if:
-/// 1. There is a problem with the threads.
-/// 1. The directory does not exist.
+/// Fails if the directory can not be read or the thread pool can not be created. The files that
+/// can not be read or parsed are skipped and listed in `errors` of the report.
 pub fn filter_tracks(
     folder: &str,
     first_lat: f32,
@@ -48,58 +85,558 @@ pub fn filter_tracks(
     second_lon: f32,
     distance: f32,
     threads: usize,
-) -> Vec<PathBuf> {
-    let now = Instant::now();
-
+) -> Result<FilterReport> {
     let area = SquaredFilter::new(
         Coordinate::new(first_lat, first_lon),
         Coordinate::new(second_lat, second_lon),
     );
 
-    let files = read_files_in_folder(folder);
-    println!("Files found: {}", files.len());
+    filter_tracks_in_area(folder, &area.into(), distance, threads)
+}
+
+/// Filter the .gpx tracks found in the provided folder to only return the tracks
+/// that have at least one point in the provided area, which can be a box or a polygon.
+///
+/// Example:
+///
+/// ```rust
+/// use gpx_geo_filter::filter_tracks_in_area;
+/// use gpx_geo_filter::model::{Coordinate, PolygonFilter};
+///
+/// static DIRECTORY: &str = "test/files";
+///
+/// let area = PolygonFilter::new(
+///     vec![
+///         Coordinate::new(49.454470, 10.954986),
+///         Coordinate::new(49.454470, 11.030173),
+///         Coordinate::new(49.506443, 10.992579),
+///     ],
+///     vec![],
+/// );
+///
+/// let files = filter_tracks_in_area(DIRECTORY, &area.into(), 300.0, 12).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Fails if the directory can not be read or the thread pool can not be created. The files that
+/// can not be read or parsed are skipped and listed in `errors` of the report.
+pub fn filter_tracks_in_area(
+    folder: &str,
+    area: &AreaFilter,
+    distance: f32,
+    threads: usize,
+) -> Result<FilterReport> {
+    let areas = [NamedArea::new("area", area.clone())];
+
+    let conditions = TrackConditions::default();
+    filter_tracks_in_named_areas(
+        &FolderScan::new(folder),
+        &areas,
+        MatchMode::Points,
+        &conditions,
+        distance,
+        threads,
+    )
+}
+
+/// Filter the .gpx tracks found in the provided folder to only return the tracks
+/// that have at least one point in any of the provided areas, together with the names of all the
+/// areas that were matched. Every file is parsed only once regardless of the number of areas. With
+/// `MatchMode::Segments` a track also matches when the line between two consecutive points crosses
+/// an area.
+///
+/// Example:
+///
+/// ```rust
+/// use gpx_geo_filter::filter_tracks_in_named_areas;
+/// use gpx_geo_filter::model::{
+///     Coordinate, FolderScan, MatchMode, NamedArea, SquaredFilter, TrackConditions,
+/// };
+///
+/// let folders = FolderScan::new("test/files");
+///
+/// let areas = vec![
+///     NamedArea::new(
+///         "Nuremberg",
+///         SquaredFilter::new(
+///             Coordinate::new(49.454470, 10.954986),
+///             Coordinate::new(49.506443, 11.030173),
+///         )
+///         .into(),
+///     ),
+///     NamedArea::new(
+///         "Fürth",
+///         SquaredFilter::new(
+///             Coordinate::new(49.455, 10.95),
+///             Coordinate::new(49.49, 11.01),
+///         )
+///         .into(),
+///     ),
+/// ];
+///
+/// // Only the tracks started in March 2023
+/// let conditions = TrackConditions {
+///     after: "2023-03-01T00:00:00+01:00".parse().ok(),
+///     before: "2023-04-01T00:00:00+02:00".parse().ok(),
+///     ..Default::default()
+/// };
+///
+/// let matches =
+///     filter_tracks_in_named_areas(&folders, &areas, MatchMode::Points, &conditions, 300.0, 12)
+///         .unwrap();
+/// for track in matches.results {
+///     println!("{:?} matched {:?} at {:?}", track.path, track.areas, track.first_matched_point);
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Fails if the directory can not be read or the thread pool can not be created. The files that
+/// can not be read or parsed are skipped and listed in `errors` of the report.
+pub fn filter_tracks_in_named_areas(
+    folders: &FolderScan,
+    areas: &[NamedArea],
+    mode: MatchMode,
+    conditions: &TrackConditions,
+    distance: f32,
+    threads: usize,
+) -> Result<FilterReport> {
+    let now = Instant::now();
+    let mut summary = FilterSummary::default();
+    let errors = Mutex::new(Vec::new());
+
+    let files = read_candidate_files(folders, conditions, threads, &mut summary, &errors)?;
 
-    let (mut files_area, files_nearby) = prefilter_files(files, &area, distance, threads);
-    println!("Prefilter: files in area: {}", files_area.len());
-    println!("Prefilter files close to the area: {}", files_nearby.len());
+    let (mut files_area, mut files_nearby, _) =
+        prefilter_files(files, areas, distance, conditions, threads, &errors)?;
 
-    let mut files = filter_tracks_outside_area(files_nearby, &area, threads);
-    println!("Filtered files close to area: {}", files.len());
+    // With several areas, a track starting in one of them may still reach any of the others, and
+    // starting in an area is not enough when a minimum time or distance inside is required
+    if areas.len() > 1 || conditions.has_minimum_in_area() {
+        files_nearby.extend(files_area.drain(..).map(|result| result.path));
+    }
+    summary.files_matched_by_prefilter = files_area.len();
+    summary.files_scanned = files_nearby.len();
 
+    let mut files =
+        filter_tracks_outside_area(files_nearby, areas, mode, conditions, threads, &errors)?;
     files.append(&mut files_area);
-    println!("Total files found: {}", files.len());
 
-    let elapsed = now.elapsed();
-    println!("Elapsed: {:.2?}", elapsed);
+    summary.files_matched = files.len();
+    summary.elapsed = now.elapsed();
 
-    files
+    Ok(FilterReport {
+        results: files,
+        summary,
+        errors: errors.into_inner().unwrap(),
+    })
 }
 
-/// Copy the list of files in the provided directory with the same name
+/// Filter the .gpx tracks found in the provided folder to only return the tracks
+/// for which the expression of included and excluded areas is true. The tracks whose first point
+/// is further than `distance` from all the areas are assumed not to touch any of them.
 ///
 /// Example:
 ///
 /// ```rust
-/// use std::path::PathBuf;
-/// use gpx_geo_filter::copy_gpx_files;
+/// use gpx_geo_filter::filter_tracks_by_area_expression;
+/// use gpx_geo_filter::model::{
+///     AreaExpression, Coordinate, FolderScan, MatchMode, NamedArea, SquaredFilter,
+///     TrackConditions,
+/// };
 ///
-/// static OUTPUT: &str = r"output";
-/// let files  = vec![PathBuf::from(r"test\files\8651674449.gpx")];
-/// copy_gpx_files(files, OUTPUT);
+/// let folders = FolderScan::new("test/files");
+///
+/// let area = |name: &str, south_west: Coordinate, north_east: Coordinate| {
+///     NamedArea::new(name, SquaredFilter::new(south_west, north_east).into())
+/// };
+/// let nuremberg = area("Nuremberg", Coordinate::new(49.45, 10.95), Coordinate::new(49.51, 11.03));
+/// let works = area("Works", Coordinate::new(49.47, 10.98), Coordinate::new(49.48, 10.99));
+///
+/// // Tracks in Nuremberg that avoided the construction works
+/// let expression = AreaExpression::AllOf(vec![
+///     AreaExpression::Include(nuremberg),
+///     AreaExpression::Exclude(works),
+/// ]);
+///
+/// let conditions = TrackConditions::default();
+/// let files = filter_tracks_by_area_expression(
+///     &folders,
+///     &expression,
+///     MatchMode::Points,
+///     &conditions,
+///     300.0,
+///     12,
+/// )
+/// .unwrap();
 /// ```
 ///
-/// # Panics
+/// # Errors
 ///
-/// Panics if:
-/// 1. There directory does not exist.
-/// 1. It is not possible to copy a file.
-pub fn copy_gpx_files(files: Vec<PathBuf>, output: &str) {
+/// Fails if the directory can not be read or the thread pool can not be created. The files that
+/// can not be read or parsed are skipped and listed in `errors` of the report.
+pub fn filter_tracks_by_area_expression(
+    folders: &FolderScan,
+    expression: &AreaExpression,
+    mode: MatchMode,
+    conditions: &TrackConditions,
+    distance: f32,
+    threads: usize,
+) -> Result<FilterReport> {
     let now = Instant::now();
+    let mut summary = FilterSummary::default();
+    let errors = Mutex::new(Vec::new());
+
+    let files = read_candidate_files(folders, conditions, threads, &mut summary, &errors)?;
+
+    let areas: Vec<NamedArea> = expression.areas().into_iter().cloned().collect();
+    let (files_area, mut files_nearby, mut files_far) =
+        prefilter_files(files, &areas, distance, conditions, threads, &errors)?;
+    files_nearby.extend(files_area.into_iter().map(|result| result.path));
 
-    for file in files {
-        copy_gpx_file(output, file);
+    // The files far from all the areas can only match an expression of excluded areas
+    if !expression.evaluate(&vec![false; areas.len()]) {
+        files_far.clear();
     }
+    summary.files_matched_by_prefilter = files_far.len();
+    summary.files_scanned = files_nearby.len();
+
+    let mut files =
+        filter_tracks_by_expression(files_nearby, expression, mode, conditions, threads, &errors)?;
+    files.extend(files_far);
+
+    summary.files_matched = files.len();
+    summary.elapsed = now.elapsed();
 
-    let elapsed = now.elapsed();
-    println!("Elapsed copping: {:.2?}", elapsed);
+    Ok(FilterReport {
+        results: files,
+        summary,
+        errors: errors.into_inner().unwrap(),
+    })
+}
+
+/// Filter the .gpx tracks found in the provided folder to only return the tracks that visit all
+/// the areas in the given order, together with the point at which each area was entered. Only the
+/// tracks starting close to the first area are scanned. With `MatchMode::Segments` an area is also
+/// entered when the line between two consecutive points crosses it.
+///
+/// Example:
+///
+/// ```rust
+/// use gpx_geo_filter::filter_tracks_by_area_sequence;
+/// use gpx_geo_filter::model::{
+///     CircleFilter, Coordinate, FolderScan, MatchMode, NamedArea, TrackConditions,
+/// };
+///
+/// let folders = FolderScan::new("test/files");
+///
+/// let circle = |name: &str, latitude: f32, longitude: f32| {
+///     let circle = CircleFilter::new(Coordinate::new(latitude, longitude), 200.0).unwrap();
+///     NamedArea::new(name, circle.into())
+/// };
+/// let areas = vec![circle("Home", 49.4478, 11.0683), circle("Office", 49.4541, 11.0779)];
+///
+/// let conditions = TrackConditions::default();
+/// let report = filter_tracks_by_area_sequence(
+///     &folders,
+///     &areas,
+///     MatchMode::Points,
+///     &conditions,
+///     300.0,
+///     12,
+/// )
+/// .unwrap();
+/// for track in report.results {
+///     println!("{:?} entered the office at {:?}", track.path, track.entries[1].time);
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Fails if no areas are given, the directory can not be read or the thread pool can not be
+/// created. The files that can not be read or parsed are skipped and listed in `errors` of the
+/// report.
+pub fn filter_tracks_by_area_sequence(
+    folders: &FolderScan,
+    areas: &[NamedArea],
+    mode: MatchMode,
+    conditions: &TrackConditions,
+    distance: f32,
+    threads: usize,
+) -> Result<FilterReport> {
+    if areas.is_empty() {
+        return Err(Error::InvalidArgument {
+            name: String::from("sequence"),
+            message: String::from("no areas are given"),
+        });
+    }
+    let now = Instant::now();
+    let mut summary = FilterSummary::default();
+    let errors = Mutex::new(Vec::new());
+
+    let files = read_candidate_files(folders, conditions, threads, &mut summary, &errors)?;
+
+    // The track has to reach the first area before any other one
+    let first_area = &areas[..1];
+    let (files_area, mut files_nearby, _) =
+        prefilter_files(files, first_area, distance, conditions, threads, &errors)?;
+    files_nearby.extend(files_area.into_iter().map(|result| result.path));
+    summary.files_scanned = files_nearby.len();
+
+    let files = filter_tracks_not_following_sequence(
+        files_nearby,
+        areas,
+        mode,
+        conditions,
+        threads,
+        &errors,
+    )?;
+
+    summary.files_matched = files.len();
+    summary.elapsed = now.elapsed();
+
+    Ok(FilterReport {
+        results: files,
+        summary,
+        errors: errors.into_inner().unwrap(),
+    })
+}
+
+/// Filter the .gpx tracks found in the provided folder to only return the tracks
+/// that follow the reference route of the corridor for at least `min_percentage` of their length.
+/// The first point is used to discard the tracks further than `distance` from the corridor.
+///
+/// Example:
+///
+/// ```rust
+/// use gpx_geo_filter::{areas::load_gpx_corridor, filter_tracks_in_corridor};
+/// use gpx_geo_filter::model::{FolderScan, TrackConditions};
+///
+/// let folders = FolderScan::new("test/files");
+///
+/// let corridor = load_gpx_corridor("test/files/8651674449.gpx", 50.0).unwrap();
+///
+/// let conditions = TrackConditions::default();
+/// let files =
+///     filter_tracks_in_corridor(&folders, &corridor, 80.0, &conditions, 300.0, 12).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Fails if the directory can not be read or the thread pool can not be created. The files that
+/// can not be read or parsed are skipped and listed in `errors` of the report.
+pub fn filter_tracks_in_corridor(
+    folders: &FolderScan,
+    corridor: &CorridorFilter,
+    min_percentage: f32,
+    conditions: &TrackConditions,
+    distance: f32,
+    threads: usize,
+) -> Result<FilterReport> {
+    let now = Instant::now();
+    let mut summary = FilterSummary::default();
+    let errors = Mutex::new(Vec::new());
+
+    let files = read_candidate_files(folders, conditions, threads, &mut summary, &errors)?;
+
+    let areas = [NamedArea::new("corridor", corridor.clone().into())];
+
+    // Starting in the corridor is not enough to follow it, so all the files need the full scan
+    let (files_area, mut files_nearby, _) =
+        prefilter_files(files, &areas, distance, conditions, threads, &errors)?;
+    files_nearby.extend(files_area.into_iter().map(|result| result.path));
+    summary.files_scanned = files_nearby.len();
+
+    let files = filter_tracks_mostly_outside_area(
+        files_nearby,
+        &areas[0],
+        min_percentage,
+        conditions,
+        threads,
+        &errors,
+    )?;
+
+    summary.files_matched = files.len();
+    summary.elapsed = now.elapsed();
+
+    Ok(FilterReport {
+        results: files,
+        summary,
+        errors: errors.into_inner().unwrap(),
+    })
+}
+
+/// Compute the statistics of the tracks of the report that matched without a full scan, like the
+/// tracks starting inside an area. Those files are parsed again, so the statistics should only be
+/// added when they are shown. The points of the kinds checked by the conditions are used
+///
+/// Example:
+///
+/// ```rust
+/// use gpx_geo_filter::{add_missing_statistics, filter_tracks_in_area};
+/// use gpx_geo_filter::model::{Coordinate, SquaredFilter, TrackConditions};
+///
+/// let area = SquaredFilter::new(Coordinate::new(-12.6, 165.4), Coordinate::new(-11.0, 167.5));
+///
+/// let mut report = filter_tracks_in_area("test/files", &area.into(), 300.0, 12).unwrap();
+/// add_missing_statistics(&mut report, &TrackConditions::default(), 12).unwrap();
+/// for track in report.results {
+///     println!("{:?}: {}", track.path, track.statistics.unwrap());
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Fails if the thread pool can not be created. The files that can no longer be read are removed
+/// from the results and listed in `errors` of the report.
+pub fn add_missing_statistics(
+    report: &mut FilterReport,
+    conditions: &TrackConditions,
+    threads: usize,
+) -> Result<()> {
+    let errors = Mutex::new(Vec::new());
+
+    let (mut results, missing): (Vec<FilterResult>, Vec<FilterResult>) = report
+        .results
+        .drain(..)
+        .partition(|result| result.statistics.is_some());
+    results.append(&mut compute_statistics_of_tracks(
+        missing, conditions, threads, &errors,
+    )?);
+
+    report.results = results;
+    report.summary.files_matched = report.results.len();
+    report.errors.append(&mut errors.into_inner().unwrap());
+    Ok(())
+}
+
+/// Read the .gpx files of the folders that meet the conditions on start time, name and type, which
+/// are checked without looking at the coordinates. The number of files is added to the summary
+fn read_candidate_files(
+    folders: &FolderScan,
+    conditions: &TrackConditions,
+    threads: usize,
+    summary: &mut FilterSummary,
+    errors: &Mutex<Vec<Error>>,
+) -> Result<Vec<PathBuf>> {
+    let mut files = read_files_in_folders(folders, errors)?;
+    summary.files_found = files.len();
+
+    if conditions.has_header_conditions() {
+        files = filter_tracks_not_meeting_conditions(files, conditions, threads, errors)?;
+    }
+    summary.files_meeting_conditions = files.len();
+    Ok(files)
+}
+
+/// Read the whole content of a .gpx file: metadata, tracks with their segments, routes and
+/// waypoints. The points keep their elevation, time, hdop and extensions.
+///
+/// Example:
+///
+/// ```rust
+/// use gpx_geo_filter::read_gpx;
+///
+/// let gpx = read_gpx("test/files/8651674449.gpx").unwrap();
+/// for track in &gpx.tracks {
+///     println!("{:?}: {} segments", track.name, track.segments.len());
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Fails if the file can not be opened or is not valid XML.
+pub fn read_gpx(path: &str) -> Result<Gpx> {
+    read_gpx_file(&PathBuf::from(path))
+}
+
+/// Copy the list of files in the provided directory, under their path relative to the scanned
+/// folder they were found in so files with the same name in different subfolders are all kept
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use std::path::{Path, PathBuf};
+/// use gpx_geo_filter::copy_gpx_files;
+/// use gpx_geo_filter::model::FolderScan;
+///
+/// let output = Path::new("output");
+/// let folders = FolderScan::new("test");
+/// let files  = vec![PathBuf::from(r"test\files\8651674449.gpx")];
+/// for error in copy_gpx_files(files, &folders, output) {
+///     println!("Not copied {error}");
+/// }
+/// ```
+///
+/// # Errors
+///
+/// The files that can not be copied, for example when the directory can not be created, are
+/// skipped and their errors returned. So are the files that would replace one already copied, e.g.
+/// files with the same relative path in two of the folders.
+pub fn copy_gpx_files(files: Vec<PathBuf>, folders: &FolderScan, output: &Path) -> Vec<Error> {
+    let mut copied = HashSet::new();
+
+    files
+        .iter()
+        .filter_map(|file| {
+            let target = output.join(relative_path(file, &folders.folders));
+            copy_gpx_file(file, target, &mut copied).err()
+        })
+        .collect()
+}
+
+/// Copy every matched file in a subfolder of the provided directory named after each of the areas
+/// it matched, under its path relative to the scanned folder it was found in. The subfolders are
+/// created if they do not exist
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use std::path::{Path, PathBuf};
+/// use gpx_geo_filter::copy_gpx_files_by_area;
+/// use std::time::Duration;
+/// use gpx_geo_filter::model::{FilterResult, FolderScan, MatchReason};
+///
+/// let output = Path::new("output");
+/// let folders = FolderScan::new("test/files");
+/// let matches = vec![FilterResult {
+///     path: PathBuf::from(r"test/files/8651674449.gpx"),
+///     reason: MatchReason::Prefilter,
+///     first_matched_point: None,
+///     matched_kind: None,
+///     areas: vec![String::from("Nuremberg")],
+///     statistics: None,
+///     properties: None,
+///     entries: Vec::new(),
+///     elapsed: Duration::ZERO,
+/// }];
+/// for error in copy_gpx_files_by_area(&matches, &folders, output) {
+///     println!("Not copied {error}");
+/// }
+/// ```
+///
+/// # Errors
+///
+/// The subfolders that can not be created and the files that can not be copied are skipped and
+/// their errors returned, like the files that would replace one already copied. The areas whose
+/// names can not be a folder, e.g. `..`, or give the same folder as another area are not copied.
+pub fn copy_gpx_files_by_area(
+    matches: &[FilterResult],
+    folders: &FolderScan,
+    output: &Path,
+) -> Vec<Error> {
+    let mut created = HashMap::new();
+    let mut copied = HashSet::new();
+    let mut errors = Vec::new();
+
+    for track in matches {
+        let relative = relative_path(&track.path, &folders.folders);
+        for area in &track.areas {
+            let result = create_area_folder(output, area, &mut created)
+                .and_then(|folder| copy_gpx_file(&track.path, folder.join(relative), &mut copied));
+            errors.extend(result.err());
+        }
+    }
+    errors
 }
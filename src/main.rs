@@ -1,26 +1,170 @@
-use gpx_geo_filter::{cli::get_cli_arguments, copy_gpx_files, filter_tracks};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    process::exit,
+    time::Instant,
+};
+
+use gpx_geo_filter::{
+    add_missing_statistics,
+    cli::get_cli_arguments,
+    config::GpxGeoFilterConfig,
+    copy_gpx_files, copy_gpx_files_by_area,
+    error::{Error, Result},
+    filter_tracks_by_area_expression, filter_tracks_by_area_sequence, filter_tracks_in_corridor,
+    filter_tracks_in_named_areas,
+    model::FilterReport,
+    output::{write_report, write_sequence_report},
+};
 
 fn main() {
     let config = get_cli_arguments();
+    if let Err(err) = run(config) {
+        eprintln!("Error: {err}");
+        exit(1);
+    }
+}
+
+/// Filter the tracks as configured and report the files that could not be read at the end
+fn run(config: GpxGeoFilterConfig) -> Result<()> {
+    let folders = &config.folders;
+    for name in &config.skipped_features {
+        eprintln!("Feature '{name}' is not a polygon and will be ignored");
+    }
+
+    let mut report = if !config.sequence.is_empty() {
+        filter_tracks_by_area_sequence(
+            folders,
+            &config.sequence,
+            config.match_mode,
+            &config.conditions,
+            config.distance,
+            config.threads,
+        )?
+    } else if let Some(corridor) = &config.corridor {
+        filter_tracks_in_corridor(
+            folders,
+            corridor,
+            config.corridor_min_percentage,
+            &config.conditions,
+            config.distance,
+            config.threads,
+        )?
+    } else if let Some(expression) = &config.expression {
+        filter_tracks_by_area_expression(
+            folders,
+            expression,
+            config.match_mode,
+            &config.conditions,
+            config.distance,
+            config.threads,
+        )?
+    } else {
+        filter_tracks_in_named_areas(
+            folders,
+            &config.areas,
+            config.match_mode,
+            &config.conditions,
+            config.distance,
+            config.threads,
+        )?
+    };
+
+    if config.output_format.shows_statistics() {
+        add_missing_statistics(&mut report, &config.conditions, config.threads)?;
+    }
+
+    print_summary(&report, &config);
+
+    let mut writer = open_output(&config)?;
+    let with_areas = config.areas.len() > 1;
+    let written = match config.sequence.is_empty() {
+        true => write_report(&mut writer, &report, config.output_format, with_areas),
+        false => write_sequence_report(&mut writer, &report, config.output_format),
+    };
+    written
+        .and_then(|_| writer.flush())
+        .map_err(|err| output_error(&config, err))?;
+
+    if let Some(path) = config.copy_to {
+        eprintln!("Copying filtered files to {:?}", path.display());
+        let now = Instant::now();
+        let by_area = config.corridor.is_none()
+            && config.expression.is_none()
+            && config.sequence.is_empty()
+            && config.areas.len() > 1;
+        let mut errors = match by_area {
+            true => copy_gpx_files_by_area(&report.results, folders, &path),
+            false => {
+                let files = report.results.into_iter().map(|track| track.path).collect();
+                copy_gpx_files(files, folders, &path)
+            }
+        };
+        report.errors.append(&mut errors);
+        eprintln!("Elapsed copying: {:.2?}", now.elapsed());
+    }
+    print_errors(&report.errors);
+    Ok(())
+}
 
-    let files = filter_tracks(
-        &config
-            .folder
-            .to_str()
-            .expect("The folder can not be parsed"),
-        config.first_lat,
-        config.first_lon,
-        config.second_lat,
-        config.second_lon,
-        config.distance,
-        config.threads,
+/// Print how many files were left after each step of the filter and, with several areas, how many
+/// touched each of them
+fn print_summary(report: &FilterReport, config: &GpxGeoFilterConfig) {
+    let summary = &report.summary;
+    eprintln!("Files found: {}", summary.files_found);
+    if config.conditions.has_header_conditions() {
+        eprintln!(
+            "Prefilter files meeting the conditions: {}",
+            summary.files_meeting_conditions
+        );
+    }
+    eprintln!(
+        "Prefilter files matched: {}",
+        summary.files_matched_by_prefilter
     );
+    eprintln!("Files scanned: {}", summary.files_scanned);
+    eprintln!("Total files found: {}", summary.files_matched);
+    if config.corridor.is_none() && config.areas.len() > 1 {
+        for area in &config.areas {
+            let count = report
+                .results
+                .iter()
+                .filter(|track| track.areas.contains(&area.name))
+                .count();
+            eprintln!("Files in '{}': {}", area.name, count);
+        }
+    }
+    eprintln!("Elapsed: {:.2?}", summary.elapsed);
+}
 
-    match config.copy_to {
+/// Writer of the matched tracks, to the output file if there is one or to stdout
+fn open_output(config: &GpxGeoFilterConfig) -> Result<Box<dyn Write>> {
+    match &config.output {
         Some(path) => {
-            println!("Copying filtered files to {:?}", path.display());
-            copy_gpx_files(files, &path.to_str().expect("Invalid output path"));
+            let file = File::create(path).map_err(|err| Error::io(path, err))?;
+            Ok(Box::new(BufWriter::new(file)))
         }
-        _ => (),
+        None => Ok(Box::new(BufWriter::new(io::stdout().lock()))),
+    }
+}
+
+fn output_error(config: &GpxGeoFilterConfig, error: io::Error) -> Error {
+    Error::io(
+        config.output.clone().unwrap_or_else(|| "stdout".into()),
+        error,
+    )
+}
+
+/// Print the files and folders that were skipped because they could not be read
+fn print_errors(errors: &[Error]) {
+    if errors.is_empty() {
+        return;
+    }
+    eprintln!(
+        "Files and folders skipped because of errors: {}",
+        errors.len()
+    );
+    for error in errors {
+        eprintln!("  {error}");
     }
 }
@@ -1,3 +1,16 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use geojson::JsonObject;
+use glob::Pattern;
+use regex::Regex;
+
+use crate::error::{Error, Result};
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct Coordinate {
     pub latitude: f32,
@@ -14,6 +27,249 @@ impl Coordinate {
 
 impl Copy for Coordinate {}
 
+/// Point of a track with the time at which it was recorded and its elevation, if the file has them
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TrackPoint {
+    pub coordinate: Coordinate,
+    pub time: Option<DateTime<Utc>>,
+    pub elevation: Option<f64>,
+    pub kind: PointKind,
+    /// Index of the line of the file that the point belongs to, e.g. a track segment or a route.
+    /// Only consecutive points of the same line are joined, every waypoint is a line of its own
+    pub line: usize,
+}
+
+impl TrackPoint {
+    pub fn new(coordinate: Coordinate, time: Option<DateTime<Utc>>) -> TrackPoint {
+        TrackPoint {
+            coordinate,
+            time,
+            elevation: None,
+            kind: PointKind::Track,
+            line: 0,
+        }
+    }
+}
+
+/// Element of a .gpx file that a point comes from. The points of the other formats are all track
+/// points
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PointKind {
+    /// A `<trkpt>` of a recorded track
+    #[default]
+    Track,
+    /// A `<rtept>` of a planned route
+    Route,
+    /// A `<wpt>`, a point of interest on its own
+    Waypoint,
+}
+
+impl PointKind {
+    /// Kind of the point of the element with this name, if it is a point at all
+    pub fn of_element(name: &[u8]) -> Option<PointKind> {
+        match name {
+            b"trkpt" => Some(PointKind::Track),
+            b"rtept" => Some(PointKind::Route),
+            b"wpt" => Some(PointKind::Waypoint),
+            _ => None,
+        }
+    }
+
+    /// Name of the .gpx element of the point, e.g. `rtept`
+    pub fn element(&self) -> &'static str {
+        match self {
+            PointKind::Track => "trkpt",
+            PointKind::Route => "rtept",
+            PointKind::Waypoint => "wpt",
+        }
+    }
+
+    /// Name of the kind as given in the arguments and written in the outputs, e.g. `route`
+    pub fn name(&self) -> &'static str {
+        match self {
+            PointKind::Track => "track",
+            PointKind::Route => "route",
+            PointKind::Waypoint => "waypoint",
+        }
+    }
+}
+
+/// Summary of a whole track
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct TrackStatistics {
+    /// Length of the track in meters, measured over the surface of the earth
+    pub distance_m: f64,
+    /// Time between the first and the last point with time
+    pub duration: Option<Duration>,
+    /// Sum of all the climbs between consecutive points with elevation
+    pub elevation_gain_m: Option<f64>,
+    /// Smallest box containing all the points
+    pub bounding_box: Option<SquaredFilter>,
+}
+
+impl fmt::Display for TrackStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2} km", self.distance_m / 1000.0)?;
+        if let Some(duration) = self.duration {
+            let seconds = duration.num_seconds();
+            write!(
+                f,
+                ", {}:{:02}:{:02}",
+                seconds / 3600,
+                seconds % 3600 / 60,
+                seconds % 60
+            )?;
+        }
+        if let Some(elevation_gain_m) = self.elevation_gain_m {
+            write!(f, ", +{:.0} m", elevation_gain_m)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a track was found to match the filter
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MatchReason {
+    /// The first point of the file was enough, without reading the rest of the track
+    Prefilter,
+    /// The points of the track were checked one by one
+    FullScan,
+}
+
+/// Track that matched the filter
+#[derive(Clone, PartialEq, Debug)]
+pub struct FilterResult {
+    pub path: PathBuf,
+    pub reason: MatchReason,
+    /// First point of the track found inside any of the areas. A track matching only because it
+    /// avoided some areas has none
+    pub first_matched_point: Option<Coordinate>,
+    /// Kind of the element of the first matched point, e.g. a route point of a .gpx file
+    pub matched_kind: Option<PointKind>,
+    /// Names of the areas touched by the track
+    pub areas: Vec<String>,
+    /// Computed while scanning the points of the track. The tracks that matched without a full
+    /// scan have none until they are added with `add_missing_statistics`
+    pub statistics: Option<TrackStatistics>,
+    /// Properties of the feature of a .geojson track, none for the other formats
+    pub properties: Option<JsonObject>,
+    /// Point at which the track entered each area of a sequence, in the same order. Empty for the
+    /// other filters
+    pub entries: Vec<TrackPoint>,
+    /// Time spent reading and checking the file
+    pub elapsed: std::time::Duration,
+}
+
+/// Number of files left after each step of the filter
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct FilterSummary {
+    pub files_found: usize,
+    /// Files left after checking their start time, name and type
+    pub files_meeting_conditions: usize,
+    /// Files that matched from their first point only
+    pub files_matched_by_prefilter: usize,
+    /// Files whose points were checked one by one
+    pub files_scanned: usize,
+    pub files_matched: usize,
+    pub elapsed: std::time::Duration,
+}
+
+/// All the tracks that matched the filter together with the summary of the run and the files,
+/// folders and archives that were skipped because they could not be read
+#[derive(Debug, Default)]
+pub struct FilterReport {
+    pub results: Vec<FilterResult>,
+    pub summary: FilterSummary,
+    pub errors: Vec<Error>,
+}
+
+/// Content of a whole .gpx file
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Gpx {
+    pub creator: Option<String>,
+    pub metadata: Option<Metadata>,
+    pub tracks: Vec<Track>,
+    pub routes: Vec<Route>,
+    pub waypoints: Vec<Waypoint>,
+}
+
+impl Gpx {
+    /// All the points of all the tracks, in the order they were recorded
+    pub fn track_points(&self) -> impl Iterator<Item = &Waypoint> {
+        self.tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .flat_map(|segment| &segment.points)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub time: Option<DateTime<Utc>>,
+}
+
+/// Recorded track, split in segments where the recording was interrupted
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Track {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Content of the `<type>` element, e.g. `running` or a numeric code in Strava exports
+    pub activity_type: Option<String>,
+    pub segments: Vec<TrackSegment>,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct TrackSegment {
+    pub points: Vec<Waypoint>,
+}
+
+/// Planned route, as a list of points to follow
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Route {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub points: Vec<Waypoint>,
+}
+
+/// Any point of a .gpx file: a track point, a route point or a waypoint. The extensions are stored
+/// by the local name of the element, e.g. `hr` for `<gpxtpx:hr>`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Waypoint {
+    pub coordinate: Coordinate,
+    pub elevation: Option<f64>,
+    pub time: Option<DateTime<Utc>>,
+    pub hdop: Option<f64>,
+    pub name: Option<String>,
+    pub extensions: HashMap<String, String>,
+}
+
+impl Waypoint {
+    pub fn new(coordinate: Coordinate) -> Waypoint {
+        Waypoint {
+            coordinate,
+            elevation: None,
+            time: None,
+            hdop: None,
+            name: None,
+            extensions: HashMap::new(),
+        }
+    }
+}
+
+impl From<&Waypoint> for TrackPoint {
+    fn from(point: &Waypoint) -> Self {
+        TrackPoint {
+            coordinate: point.coordinate,
+            time: point.time,
+            elevation: point.elevation,
+            kind: PointKind::Track,
+            line: 0,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Debug)]
 
 pub struct SquaredFilter {
@@ -24,6 +280,8 @@ pub struct SquaredFilter {
 }
 
 impl SquaredFilter {
+    /// The first point is the south-west corner and the second the north-east one. When the west
+    /// edge is east of the east edge the box wraps around the antimeridian
     pub fn new(a: Coordinate, b: Coordinate) -> SquaredFilter {
         SquaredFilter {
             top_left: Coordinate::new(b.latitude, a.longitude),
@@ -32,6 +290,501 @@ impl SquaredFilter {
             bottom_left: a,
         }
     }
+
+    /// Check if the box goes over the 180th meridian, e.g. from 170 to -170
+    pub fn crosses_antimeridian(&self) -> bool {
+        self.bottom_left.longitude > self.top_right.longitude
+    }
+
+    /// Check if the longitude is between the west and east edges, including the west one
+    pub fn contains_longitude(&self, longitude: f32) -> bool {
+        let west = self.bottom_left.longitude;
+        let east = self.top_right.longitude;
+        match self.crosses_antimeridian() {
+            true => longitude >= west || longitude < east,
+            false => longitude >= west && longitude < east,
+        }
+    }
+}
+
+/// Polygon defined by an exterior ring and an optional list of holes. The rings may be open or
+/// closed (first point repeated at the end)
+#[derive(Clone, PartialEq, Debug)]
+pub struct PolygonFilter {
+    pub exterior: Vec<Coordinate>,
+    pub holes: Vec<Vec<Coordinate>>,
+}
+
+impl PolygonFilter {
+    pub fn new(exterior: Vec<Coordinate>, holes: Vec<Vec<Coordinate>>) -> PolygonFilter {
+        PolygonFilter { exterior, holes }
+    }
+
+    /// Smallest box containing the exterior ring of the polygon, none when the ring is empty
+    pub fn bounding_box(&self) -> Option<SquaredFilter> {
+        let mut south_west = *self.exterior.first()?;
+        let mut north_east = south_west;
+
+        for point in &self.exterior {
+            south_west.latitude = f32::min(south_west.latitude, point.latitude);
+            south_west.longitude = f32::min(south_west.longitude, point.longitude);
+            north_east.latitude = f32::max(north_east.latitude, point.latitude);
+            north_east.longitude = f32::max(north_east.longitude, point.longitude);
+        }
+
+        Some(SquaredFilter::new(south_west, north_east))
+    }
+}
+
+/// Circle of the given radius in meters around a point
+#[derive(Clone, PartialEq, Debug)]
+pub struct CircleFilter {
+    pub center: Coordinate,
+    pub radius_m: f64,
+}
+
+impl CircleFilter {
+    /// # Errors
+    ///
+    /// Fails if the radius is not a number of meters greater than 0.
+    pub fn new(center: Coordinate, radius_m: f64) -> Result<CircleFilter> {
+        if !(radius_m.is_finite() && radius_m > 0.0) {
+            return Err(Error::InvalidArgument {
+                name: String::from("radius_m"),
+                message: format!("{radius_m} is not a distance greater than 0"),
+            });
+        }
+        Ok(CircleFilter { center, radius_m })
+    }
+
+    /// Box containing the circle
+    pub fn bounding_box(&self) -> SquaredFilter {
+        let (delta_latitude, delta_longitude) =
+            buffer_in_degrees(self.center.latitude, self.radius_m);
+        let center = SquaredFilter::new(self.center, self.center);
+
+        extend_box(&center, delta_latitude, delta_longitude)
+    }
+}
+
+/// Extend the box by the given degrees on every side. The latitudes stop at the poles, and a box
+/// that reaches a pole or goes all around the earth covers every longitude
+fn extend_box(area: &SquaredFilter, delta_latitude: f64, delta_longitude: f64) -> SquaredFilter {
+    let south = area.bottom_left.latitude as f64 - delta_latitude;
+    let north = area.top_right.latitude as f64 + delta_latitude;
+    let west = area.bottom_left.longitude as f64;
+    let east = area.top_right.longitude as f64;
+    let width = match area.crosses_antimeridian() {
+        true => east - west + 360.0,
+        false => east - west,
+    };
+
+    let all_around = south <= -90.0 || north >= 90.0 || width + 2.0 * delta_longitude >= 360.0;
+    let (west, east) = match all_around {
+        true => (-180.0, 180.0),
+        false => (
+            wrap_longitude(west - delta_longitude),
+            wrap_longitude(east + delta_longitude),
+        ),
+    };
+    SquaredFilter::new(
+        Coordinate::new(south.max(-90.0) as f32, west),
+        Coordinate::new(north.min(90.0) as f32, east),
+    )
+}
+
+/// Bring a longitude that went over the antimeridian back to the range [-180, 180]
+fn wrap_longitude(longitude: f64) -> f32 {
+    match longitude {
+        longitude if longitude > 180.0 => (longitude - 360.0) as f32,
+        longitude if longitude < -180.0 => (longitude + 360.0) as f32,
+        longitude => longitude as f32,
+    }
+}
+
+/// Convert a distance in meters to degrees of latitude and longitude at the given latitude. One
+/// degree of latitude is around 111 km, while the length of a degree of longitude shrinks with the
+/// cosine of the latitude
+fn buffer_in_degrees(latitude: f32, buffer_m: f64) -> (f64, f64) {
+    const METERS_PER_DEGREE: f64 = 111_320.0;
+
+    let delta_latitude = buffer_m / METERS_PER_DEGREE;
+    let delta_longitude = (delta_latitude / (latitude as f64).to_radians().cos()).min(180.0);
+    (delta_latitude, delta_longitude)
+}
+
+/// Area around a reference route, formed by all the points closer than `buffer_m` meters to any
+/// of its segments
+#[derive(Clone, PartialEq, Debug)]
+pub struct CorridorFilter {
+    pub route: Vec<Coordinate>,
+    pub buffer_m: f64,
+}
+
+impl CorridorFilter {
+    /// # Errors
+    ///
+    /// Fails if the buffer is not a number of meters, or is negative.
+    pub fn new(route: Vec<Coordinate>, buffer_m: f64) -> Result<CorridorFilter> {
+        if !(buffer_m.is_finite() && buffer_m >= 0.0) {
+            return Err(Error::InvalidArgument {
+                name: String::from("buffer_m"),
+                message: format!("{buffer_m} is not a distance of 0 or more"),
+            });
+        }
+        Ok(CorridorFilter { route, buffer_m })
+    }
+
+    /// Box containing the route extended by the buffer in every direction. The buffer in
+    /// longitude is computed at the latitude furthest from the equator, where it is the widest.
+    /// None when the route is empty, `load_gpx_corridor` requires two points
+    pub fn bounding_box(&self) -> Option<SquaredFilter> {
+        let route = PolygonFilter::new(self.route.clone(), vec![]).bounding_box()?;
+        let widest_latitude = f32::max(
+            route.bottom_left.latitude.abs(),
+            route.top_right.latitude.abs(),
+        );
+        let (delta_latitude, delta_longitude) = buffer_in_degrees(widest_latitude, self.buffer_m);
+
+        Some(extend_box(&route, delta_latitude, delta_longitude))
+    }
+}
+
+/// Any of the supported shapes that can be used to filter the tracks
+#[derive(Clone, PartialEq, Debug)]
+pub enum AreaFilter {
+    Squared(SquaredFilter),
+    Polygon(PolygonFilter),
+    MultiPolygon(Vec<PolygonFilter>),
+    Circle(CircleFilter),
+    Corridor(CorridorFilter),
+}
+
+impl AreaFilter {
+    /// Smallest box containing the area, used to estimate the distance of a point to the area.
+    /// None when the area has no points, e.g. a polygon with an empty ring
+    pub fn bounding_box(&self) -> Option<SquaredFilter> {
+        match self {
+            AreaFilter::Squared(area) => Some(area.clone()),
+            AreaFilter::Polygon(polygon) => polygon.bounding_box(),
+            AreaFilter::MultiPolygon(polygons) => {
+                let exterior = polygons
+                    .iter()
+                    .flat_map(|polygon| polygon.exterior.iter().copied())
+                    .collect();
+                PolygonFilter::new(exterior, vec![]).bounding_box()
+            }
+            AreaFilter::Circle(circle) => Some(circle.bounding_box()),
+            AreaFilter::Corridor(corridor) => corridor.bounding_box(),
+        }
+    }
+}
+
+impl From<SquaredFilter> for AreaFilter {
+    fn from(area: SquaredFilter) -> Self {
+        AreaFilter::Squared(area)
+    }
+}
+
+impl From<PolygonFilter> for AreaFilter {
+    fn from(polygon: PolygonFilter) -> Self {
+        AreaFilter::Polygon(polygon)
+    }
+}
+
+impl From<CircleFilter> for AreaFilter {
+    fn from(circle: CircleFilter) -> Self {
+        AreaFilter::Circle(circle)
+    }
+}
+
+impl From<CorridorFilter> for AreaFilter {
+    fn from(corridor: CorridorFilter) -> Self {
+        AreaFilter::Corridor(corridor)
+    }
+}
+
+/// How the points of a track are compared against the areas
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum MatchMode {
+    /// A track matches when one of its recorded points is in the area
+    #[default]
+    Points,
+    /// A track also matches when the line between two consecutive points crosses the area, so
+    /// tracks with a low sampling rate do not skip small areas
+    Segments,
+}
+
+/// Area with the name used to report which region a track matched
+#[derive(Clone, PartialEq, Debug)]
+pub struct NamedArea {
+    pub name: String,
+    pub area: AreaFilter,
+}
+
+impl NamedArea {
+    pub fn new(name: &str, area: AreaFilter) -> NamedArea {
+        NamedArea {
+            name: String::from(name),
+            area,
+        }
+    }
+}
+
+/// Combination of areas that a track must touch, or avoid, to be kept
+#[derive(Clone, PartialEq, Debug)]
+pub enum AreaExpression {
+    /// The track touches the area
+    Include(NamedArea),
+    /// The track never touches the area
+    Exclude(NamedArea),
+    /// All the expressions are true
+    AllOf(Vec<AreaExpression>),
+    /// At least one of the expressions is true
+    AnyOf(Vec<AreaExpression>),
+}
+
+impl AreaExpression {
+    /// All the areas used in the expression, in the order in which they are evaluated
+    pub fn areas(&self) -> Vec<&NamedArea> {
+        match self {
+            AreaExpression::Include(area) | AreaExpression::Exclude(area) => vec![area],
+            AreaExpression::AllOf(expressions) | AreaExpression::AnyOf(expressions) => expressions
+                .iter()
+                .flat_map(|expression| expression.areas())
+                .collect(),
+        }
+    }
+
+    /// Evaluate the expression given which of the areas returned by `areas` were touched
+    pub fn evaluate(&self, touched: &[bool]) -> bool {
+        let mut index = 0;
+        self.evaluate_from(touched, &mut index)
+    }
+
+    fn evaluate_from(&self, touched: &[bool], index: &mut usize) -> bool {
+        match self {
+            AreaExpression::Include(_) => {
+                *index += 1;
+                touched[*index - 1]
+            }
+            AreaExpression::Exclude(_) => {
+                *index += 1;
+                !touched[*index - 1]
+            }
+            // All the children are evaluated, without short circuit, to keep the index in sync
+            // with `areas`
+            AreaExpression::AllOf(expressions) => expressions
+                .iter()
+                .map(|expression| expression.evaluate_from(touched, index))
+                .collect::<Vec<bool>>()
+                .into_iter()
+                .all(|result| result),
+            AreaExpression::AnyOf(expressions) => expressions
+                .iter()
+                .map(|expression| expression.evaluate_from(touched, index))
+                .collect::<Vec<bool>>()
+                .into_iter()
+                .any(|result| result),
+        }
+    }
+}
+
+/// Kind of activity of a track, from the `<type>` element. Strava writes numeric codes while other
+/// apps write names like `running`
+#[derive(Clone, PartialEq, Debug)]
+pub enum ActivityType {
+    Ride,
+    Run,
+    Walk,
+    Hike,
+    Swim,
+    /// Any other type, as written in the file
+    Other(String),
+}
+
+impl ActivityType {
+    /// Map the content of a `<type>` element, or a name given by the user, to the activity type
+    pub fn parse(value: &str) -> ActivityType {
+        match value.trim().to_lowercase().as_str() {
+            "1" | "ride" | "cycling" | "biking" | "road_biking" | "mountain_biking" => {
+                ActivityType::Ride
+            }
+            "9" | "run" | "running" | "trail_running" => ActivityType::Run,
+            "10" | "walk" | "walking" => ActivityType::Walk,
+            "4" | "hike" | "hiking" => ActivityType::Hike,
+            "16" | "swim" | "swimming" | "open_water_swimming" => ActivityType::Swim,
+            other => ActivityType::Other(other.to_string()),
+        }
+    }
+}
+
+/// Information at the start of a .gpx file, before the coordinates of the track
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct TrackHeader {
+    /// Time in `<metadata>` or otherwise the time of the first point
+    pub start_time: Option<DateTime<Utc>>,
+    /// Name of the track or otherwise the one in `<metadata>`
+    pub name: Option<String>,
+    pub activity_type: Option<ActivityType>,
+}
+
+/// Time spent and distance travelled by a track inside an area
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AreaVisit {
+    pub duration: Duration,
+    pub distance_m: f64,
+}
+
+/// Conditions on a track besides touching the areas. The default one accepts every track
+#[derive(Clone, Debug, Default)]
+pub struct TrackConditions {
+    /// The track must have started at this time or later
+    pub after: Option<DateTime<Utc>>,
+    /// The track must have started before this time
+    pub before: Option<DateTime<Utc>>,
+    /// The track must be of any of these types, when there is at least one
+    pub activity_types: Vec<ActivityType>,
+    /// The name of the track must match this pattern
+    pub name: Option<Regex>,
+    /// An area only counts as touched when the track spent at least this time inside
+    pub min_time_in_area: Option<Duration>,
+    /// An area only counts as touched when the track travelled at least this distance inside
+    pub min_distance_in_area_m: Option<f64>,
+    /// Kinds of the points of .gpx files that are checked against the areas, only the track
+    /// points when there is none
+    pub point_kinds: Vec<PointKind>,
+}
+
+/// `Regex` has no equality, two name patterns are equal when they were compiled from the same text
+impl PartialEq for TrackConditions {
+    fn eq(&self, other: &Self) -> bool {
+        self.after == other.after
+            && self.before == other.before
+            && self.activity_types == other.activity_types
+            && self.name.as_ref().map(Regex::as_str) == other.name.as_ref().map(Regex::as_str)
+            && self.min_time_in_area == other.min_time_in_area
+            && self.min_distance_in_area_m == other.min_distance_in_area_m
+            && self.point_kinds == other.point_kinds
+    }
+}
+
+impl TrackConditions {
+    /// Check if there is any condition on the start time, name or type, which need the header of
+    /// the files
+    pub fn has_header_conditions(&self) -> bool {
+        self.has_time_range() || !self.activity_types.is_empty() || self.name.is_some()
+    }
+
+    /// Check if there is a minimum time or distance inside the areas, which needs all the points
+    pub fn has_minimum_in_area(&self) -> bool {
+        self.min_time_in_area.is_some() || self.min_distance_in_area_m.is_some()
+    }
+
+    /// Check if the visit to an area was long enough for both minimums
+    pub fn is_visit_long_enough(&self, visit: &AreaVisit) -> bool {
+        self.min_time_in_area
+            .is_none_or(|min_time| visit.duration >= min_time)
+            && self
+                .min_distance_in_area_m
+                .is_none_or(|min_distance| visit.distance_m >= min_distance)
+    }
+
+    /// Check if the points of this kind are checked against the areas
+    pub fn checks_kind(&self, kind: PointKind) -> bool {
+        self.checked_kinds().contains(&kind)
+    }
+
+    /// Kinds of the points that are checked against the areas
+    pub fn checked_kinds(&self) -> &[PointKind] {
+        match self.point_kinds.is_empty() {
+            true => &[PointKind::Track],
+            false => &self.point_kinds,
+        }
+    }
+
+    pub fn has_time_range(&self) -> bool {
+        self.after.is_some() || self.before.is_some()
+    }
+
+    /// Check if the track meets all the conditions. Tracks without the information needed by a
+    /// condition do not meet it
+    pub fn is_met_by(&self, header: &TrackHeader) -> bool {
+        let activity_type_matches = self.activity_types.is_empty()
+            || header
+                .activity_type
+                .as_ref()
+                .is_some_and(|activity_type| self.activity_types.contains(activity_type));
+        let name_matches = self.name.as_ref().is_none_or(|pattern| {
+            header
+                .name
+                .as_ref()
+                .is_some_and(|name| pattern.is_match(name))
+        });
+
+        self.is_start_time_in_range(header.start_time) && activity_type_matches && name_matches
+    }
+
+    /// Check if a track starting at the given time is in the range. Tracks without time are only
+    /// accepted when there is no range
+    pub fn is_start_time_in_range(&self, start_time: Option<DateTime<Utc>>) -> bool {
+        let Some(start_time) = start_time else {
+            return !self.has_time_range();
+        };
+        self.after.is_none_or(|after| start_time >= after)
+            && self.before.is_none_or(|before| start_time < before)
+    }
+}
+
+/// What to do with the symbolic links found in the folders
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum SymlinkPolicy {
+    /// Skip all the links
+    Ignore,
+    /// Read the links to files but do not enter the links to folders
+    #[default]
+    Files,
+    /// Read the links to files and enter the links to folders, except those that make a loop
+    Follow,
+}
+
+/// Folders where the tracks are searched and which of their files are read
+#[derive(Clone, Debug, Default)]
+pub struct FolderScan {
+    pub folders: Vec<PathBuf>,
+    /// Levels of subfolders to enter, 0 only reads the files directly in the folders
+    pub max_depth: usize,
+    pub symlinks: SymlinkPolicy,
+    /// When not empty, only the files whose path relative to the folder matches one of them
+    pub include: Vec<Pattern>,
+    /// Files and subfolders whose path relative to the folder matches one of them are skipped
+    pub exclude: Vec<Pattern>,
+}
+
+impl FolderScan {
+    /// Read only the files directly in the folder, without patterns
+    pub fn new<P: Into<PathBuf>>(folder: P) -> FolderScan {
+        FolderScan {
+            folders: vec![folder.into()],
+            ..Default::default()
+        }
+    }
+
+    pub fn is_excluded(&self, relative_path: &Path) -> bool {
+        self.exclude
+            .iter()
+            .any(|pattern| pattern.matches_path(relative_path))
+    }
+
+    /// Check the patterns with the path of the file relative to the folder
+    pub fn is_selected(&self, relative_path: &Path) -> bool {
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| pattern.matches_path(relative_path));
+        included && !self.is_excluded(relative_path)
+    }
 }
 
 #[cfg(test)]
@@ -64,4 +817,239 @@ mod tests {
         assert_eq!(area.top_right, coordinate_b);
         assert_eq!(area.bottom_left, coordinate_a);
     }
+
+    #[test]
+    fn polygon_bounding_box() {
+        let polygon = PolygonFilter::new(
+            vec![
+                Coordinate::new(40.0, 45.0),
+                Coordinate::new(60.0, 40.0),
+                Coordinate::new(80.0, 50.0),
+                Coordinate::new(50.0, 80.0),
+            ],
+            vec![],
+        );
+
+        let area = polygon.bounding_box().unwrap();
+
+        assert_eq!(area.bottom_left, Coordinate::new(40.0, 40.0));
+        assert_eq!(area.top_right, Coordinate::new(80.0, 80.0));
+    }
+
+    #[test]
+    fn empty_area_has_no_bounding_box() {
+        let polygon = PolygonFilter::new(vec![], vec![]);
+
+        assert_eq!(None, polygon.bounding_box());
+        assert_eq!(None, AreaFilter::Polygon(polygon).bounding_box());
+        assert_eq!(None, AreaFilter::MultiPolygon(vec![]).bounding_box());
+    }
+
+    #[test]
+    fn filter_crosses_antimeridian() {
+        let fiji = SquaredFilter::new(
+            Coordinate::new(-21.0, 176.0),
+            Coordinate::new(-12.0, -178.0),
+        );
+        let nuremberg = SquaredFilter::new(
+            Coordinate::new(49.454470, 10.954986),
+            Coordinate::new(49.506443, 11.030173),
+        );
+
+        assert!(fiji.crosses_antimeridian());
+        assert!(!nuremberg.crosses_antimeridian());
+    }
+
+    #[test]
+    fn circle_bounding_box_crosses_antimeridian() {
+        let circle = CircleFilter::new(Coordinate::new(0.0, 179.9), 22_264.0).unwrap();
+
+        let area = circle.bounding_box();
+
+        assert!(area.crosses_antimeridian());
+        assert_eq!(area.bottom_left.longitude, 179.7);
+        assert!((area.top_right.longitude + 179.9).abs() < 1e-4);
+    }
+
+    #[test]
+    fn evaluate_area_expression() {
+        let area = |name: &str| {
+            NamedArea::new(
+                name,
+                SquaredFilter::new(Coordinate::new(0.0, 0.0), Coordinate::new(1.0, 1.0)).into(),
+            )
+        };
+        // A and (B or C) but never D
+        let expression = AreaExpression::AllOf(vec![
+            AreaExpression::Include(area("A")),
+            AreaExpression::AnyOf(vec![
+                AreaExpression::Include(area("B")),
+                AreaExpression::Include(area("C")),
+            ]),
+            AreaExpression::Exclude(area("D")),
+        ]);
+
+        let names: Vec<&str> = expression
+            .areas()
+            .iter()
+            .map(|area| area.name.as_str())
+            .collect();
+        assert_eq!(vec!["A", "B", "C", "D"], names);
+
+        assert!(expression.evaluate(&[true, false, true, false]));
+        assert!(expression.evaluate(&[true, true, false, false]));
+        assert!(!expression.evaluate(&[true, false, false, false]));
+        assert!(!expression.evaluate(&[true, true, true, true]));
+        assert!(!expression.evaluate(&[false, true, true, false]));
+    }
+
+    #[test]
+    fn circle_bounding_box() {
+        let circle = CircleFilter::new(Coordinate::new(60.0, 10.0), 11_132.0).unwrap();
+
+        let area = circle.bounding_box();
+
+        assert_eq!(area.bottom_left, Coordinate::new(59.9, 9.8));
+        assert_eq!(area.top_right, Coordinate::new(60.1, 10.2));
+    }
+
+    #[test]
+    fn reject_invalid_radius() {
+        let center = Coordinate::new(60.0, 10.0);
+
+        for radius_m in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                CircleFilter::new(center, radius_m),
+                Err(Error::InvalidArgument { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn reject_invalid_buffer() {
+        let route = vec![Coordinate::new(60.0, 10.0), Coordinate::new(60.1, 10.0)];
+
+        assert!(CorridorFilter::new(route.clone(), 0.0).is_ok());
+        for buffer_m in [-1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                CorridorFilter::new(route.clone(), buffer_m),
+                Err(Error::InvalidArgument { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn bounding_box_reaches_the_pole() {
+        // 20 km around a point 10 km from the north pole
+        let circle = CircleFilter::new(Coordinate::new(89.91, 10.0), 20_000.0).unwrap();
+
+        let area = circle.bounding_box();
+
+        assert_eq!(90.0, area.top_right.latitude);
+        assert_eq!(-180.0, area.bottom_left.longitude);
+        assert_eq!(180.0, area.top_right.longitude);
+        assert!(area.contains_longitude(-170.0));
+
+        // A route around the earth with a buffer wider than what is left of it
+        let corridor = CorridorFilter::new(
+            vec![Coordinate::new(70.0, -179.0), Coordinate::new(70.0, 179.0)],
+            400_000.0,
+        )
+        .unwrap();
+        let area = corridor.bounding_box().unwrap();
+        assert!(!area.crosses_antimeridian());
+        assert!(area.contains_longitude(0.0));
+    }
+
+    #[test]
+    fn start_time_in_range() {
+        let time = |text: &str| Some(text.parse::<DateTime<Utc>>().unwrap());
+        let conditions = TrackConditions {
+            after: time("2023-03-01T00:00:00Z"),
+            before: time("2023-04-01T00:00:00Z"),
+            ..Default::default()
+        };
+
+        assert!(conditions.is_start_time_in_range(time("2023-03-01T00:00:00Z")));
+        assert!(conditions.is_start_time_in_range(time("2023-03-15T08:00:00Z")));
+        assert!(!conditions.is_start_time_in_range(time("2023-02-28T23:59:59Z")));
+        assert!(!conditions.is_start_time_in_range(time("2023-04-01T00:00:00Z")));
+        assert!(!conditions.is_start_time_in_range(None));
+        assert!(TrackConditions::default().is_start_time_in_range(None));
+    }
+
+    #[test]
+    fn parse_activity_type() {
+        assert_eq!(ActivityType::Walk, ActivityType::parse("10"));
+        assert_eq!(ActivityType::Ride, ActivityType::parse("Cycling"));
+        assert_eq!(ActivityType::Run, ActivityType::parse(" run "));
+        assert_eq!(
+            ActivityType::Other(String::from("kayaking")),
+            ActivityType::parse("Kayaking")
+        );
+    }
+
+    #[test]
+    fn conditions_on_type_and_name() {
+        let header = TrackHeader {
+            start_time: None,
+            name: Some(String::from("Morning Walk")),
+            activity_type: Some(ActivityType::Walk),
+        };
+
+        let conditions = TrackConditions {
+            activity_types: vec![ActivityType::Ride, ActivityType::Walk],
+            name: Regex::new("(?i)morning").ok(),
+            ..Default::default()
+        };
+        assert!(conditions.is_met_by(&header));
+
+        let conditions = TrackConditions {
+            activity_types: vec![ActivityType::Ride],
+            ..Default::default()
+        };
+        assert!(!conditions.is_met_by(&header));
+
+        let conditions = TrackConditions {
+            name: Regex::new("^Evening").ok(),
+            ..Default::default()
+        };
+        assert!(!conditions.is_met_by(&header));
+        assert!(!conditions.is_met_by(&TrackHeader::default()));
+        assert!(TrackConditions::default().is_met_by(&TrackHeader::default()));
+    }
+
+    #[test]
+    fn compare_conditions() {
+        let conditions = |pattern: &str| TrackConditions {
+            name: Regex::new(pattern).ok(),
+            ..Default::default()
+        };
+
+        assert_eq!(conditions("^Morning"), conditions("^Morning"));
+        assert_ne!(conditions("^Morning"), conditions("^Evening"));
+        assert_ne!(conditions("^Morning"), TrackConditions::default());
+    }
+
+    #[test]
+    fn visit_long_enough() {
+        let visit = AreaVisit {
+            duration: Duration::minutes(30),
+            distance_m: 2_000.0,
+        };
+
+        let conditions = TrackConditions {
+            min_time_in_area: Some(Duration::minutes(10)),
+            min_distance_in_area_m: Some(1_000.0),
+            ..Default::default()
+        };
+        assert!(conditions.is_visit_long_enough(&visit));
+
+        let conditions = TrackConditions {
+            min_time_in_area: Some(Duration::hours(1)),
+            ..Default::default()
+        };
+        assert!(!conditions.is_visit_long_enough(&visit));
+        assert!(TrackConditions::default().is_visit_long_enough(&visit));
+    }
 }
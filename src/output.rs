@@ -0,0 +1,400 @@
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use serde_json::{json, Number, Value};
+
+use crate::model::{
+    FilterReport, FilterResult, FilterSummary, MatchReason, SquaredFilter, TrackStatistics,
+};
+
+/// Format in which the matched tracks are written
+#[derive(Clone, Copy, PartialEq, Debug, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// One line per track with its statistics, for people
+    #[default]
+    Text,
+    /// A single JSON object with the tracks, the summary of the run and the skipped files
+    Json,
+    /// One row per track with a header
+    Csv,
+    /// One JSON object per track and line
+    Ndjson,
+    /// Only the path of every track, one per line
+    Paths,
+}
+
+impl OutputFormat {
+    /// Whether the statistics of the tracks are written, so they must be computed for the tracks
+    /// that matched without a full scan
+    pub fn shows_statistics(self) -> bool {
+        self != OutputFormat::Paths
+    }
+}
+
+const CSV_HEADER: &str = "path,match,first_lat,first_lon,first_kind,areas,distance_m,duration_s,\
+elevation_gain_m,min_lat,min_lon,max_lat,max_lon,properties";
+
+/// Write the matched tracks of the report in the given format. With `with_areas` the text format
+/// also lists the areas that every track touched
+///
+/// Example:
+///
+/// ```rust
+/// use gpx_geo_filter::{filter_tracks, output::{write_report, OutputFormat}};
+///
+/// let report = filter_tracks("test/files", -12.6, 165.4, -11.0, 167.5, 300.0, 4).unwrap();
+/// write_report(&mut std::io::stdout(), &report, OutputFormat::Csv, false).unwrap();
+/// ```
+pub fn write_report<W: Write>(
+    writer: &mut W,
+    report: &FilterReport,
+    format: OutputFormat,
+    with_areas: bool,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            for track in &report.results {
+                write!(writer, "{}", track.path.display())?;
+                if let Some(statistics) = &track.statistics {
+                    write!(writer, ": {statistics}")?;
+                }
+                match with_areas {
+                    true => writeln!(writer, " matched '{}'", track.areas.join("', '"))?,
+                    false => writeln!(writer)?,
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let value = json!({
+                "files": report.results.iter().map(result_to_json).collect::<Vec<_>>(),
+                "summary": summary_to_json(&report.summary),
+                "errors": report.errors.iter().map(|error| error.to_string()).collect::<Vec<_>>(),
+            });
+            writeln!(writer, "{}", serde_json::to_string_pretty(&value)?)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(writer, "{CSV_HEADER}")?;
+            for track in &report.results {
+                writeln!(writer, "{}", result_to_csv(track))?;
+            }
+        }
+        OutputFormat::Ndjson => {
+            for track in &report.results {
+                writeln!(writer, "{}", result_to_json(track))?;
+            }
+        }
+        OutputFormat::Paths => {
+            for track in &report.results {
+                writeln!(writer, "{}", track.path.display())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write the tracks of the report that visited the areas in order, together with the point at
+/// which each area was entered
+pub fn write_sequence_report<W: Write>(
+    writer: &mut W,
+    report: &FilterReport,
+    format: OutputFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            for track in &report.results {
+                let visits: Vec<String> = track
+                    .areas
+                    .iter()
+                    .zip(&track.entries)
+                    .map(|(area, entry)| match entry.time {
+                        Some(time) => format!("'{}' at {}", area, time.to_rfc3339()),
+                        None => format!("'{}'", area),
+                    })
+                    .collect();
+                writeln!(
+                    writer,
+                    "{} entered {}",
+                    track.path.display(),
+                    visits.join(", ")
+                )?;
+            }
+        }
+        OutputFormat::Json => {
+            let value = json!({
+                "files": report.results.iter().map(sequence_to_json).collect::<Vec<_>>(),
+                "summary": summary_to_json(&report.summary),
+                "errors": report.errors.iter().map(|error| error.to_string()).collect::<Vec<_>>(),
+            });
+            writeln!(writer, "{}", serde_json::to_string_pretty(&value)?)?;
+        }
+        OutputFormat::Ndjson => {
+            for track in &report.results {
+                writeln!(writer, "{}", sequence_to_json(track))?;
+            }
+        }
+        OutputFormat::Csv => {
+            writeln!(writer, "path,area,lat,lon,time")?;
+            for track in &report.results {
+                for (area, entry) in track.areas.iter().zip(&track.entries) {
+                    let time = entry.time.map(|time| time.to_rfc3339());
+                    writeln!(
+                        writer,
+                        "{},{},{},{},{}",
+                        csv_field(&track.path.display().to_string()),
+                        csv_field(area),
+                        entry.coordinate.latitude,
+                        entry.coordinate.longitude,
+                        time.unwrap_or_default()
+                    )?;
+                }
+            }
+        }
+        OutputFormat::Paths => {
+            for track in &report.results {
+                writeln!(writer, "{}", track.path.display())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn sequence_to_json(track: &FilterResult) -> Value {
+    let entries: Vec<Value> = track
+        .areas
+        .iter()
+        .zip(&track.entries)
+        .map(|(area, entry)| {
+            json!({
+                "area": area,
+                "lat": degrees(entry.coordinate.latitude),
+                "lon": degrees(entry.coordinate.longitude),
+                "time": entry.time.map(|time| time.to_rfc3339()),
+            })
+        })
+        .collect();
+    json!({
+        "path": track.path.display().to_string(),
+        "entries": entries,
+        "properties": track.properties,
+    })
+}
+
+fn summary_to_json(summary: &FilterSummary) -> Value {
+    json!({
+        "files_found": summary.files_found,
+        "files_meeting_conditions": summary.files_meeting_conditions,
+        "files_matched_by_prefilter": summary.files_matched_by_prefilter,
+        "files_scanned": summary.files_scanned,
+        "files_matched": summary.files_matched,
+        "elapsed_s": summary.elapsed.as_secs_f64(),
+    })
+}
+
+/// Keep the digits of the `f32` instead of those of its conversion to `f64`
+fn degrees(value: f32) -> Value {
+    value
+        .to_string()
+        .parse::<Number>()
+        .map_or(Value::Null, Value::Number)
+}
+
+fn match_reason_name(reason: MatchReason) -> &'static str {
+    match reason {
+        MatchReason::Prefilter => "prefilter",
+        MatchReason::FullScan => "full_scan",
+    }
+}
+
+fn result_to_json(track: &FilterResult) -> Value {
+    let statistics = track.statistics.as_ref();
+    json!({
+        "path": track.path.display().to_string(),
+        "match": match_reason_name(track.reason),
+        "first_point": track.first_matched_point.map(|point| {
+            json!({
+                "lat": degrees(point.latitude),
+                "lon": degrees(point.longitude),
+                "kind": track.matched_kind.map(|kind| kind.name()),
+            })
+        }),
+        "areas": track.areas,
+        "distance_m": statistics.map(|statistics| statistics.distance_m),
+        "duration_s": statistics
+            .and_then(|statistics| statistics.duration)
+            .map(|duration| duration.num_seconds()),
+        "elevation_gain_m": statistics.and_then(|statistics| statistics.elevation_gain_m),
+        "bounding_box": bounding_box(statistics).map(|bounding_box| {
+            json!({
+                "min_lat": degrees(bounding_box.bottom_left.latitude),
+                "min_lon": degrees(bounding_box.bottom_left.longitude),
+                "max_lat": degrees(bounding_box.top_right.latitude),
+                "max_lon": degrees(bounding_box.top_right.longitude),
+            })
+        }),
+        "properties": track.properties,
+    })
+}
+
+fn result_to_csv(track: &FilterResult) -> String {
+    let statistics = track.statistics.as_ref();
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    let point = track.first_matched_point;
+    let bounding_box = bounding_box(statistics);
+    // The properties of a .geojson track are kept as a single JSON object
+    let properties = track
+        .properties
+        .as_ref()
+        .map(|properties| Value::Object(properties.clone()).to_string());
+    [
+        csv_field(&track.path.display().to_string()),
+        String::from(match_reason_name(track.reason)),
+        optional(point.map(|point| point.latitude.to_string())),
+        optional(point.map(|point| point.longitude.to_string())),
+        optional(track.matched_kind.map(|kind| String::from(kind.name()))),
+        csv_field(&track.areas.join(";")),
+        optional(statistics.map(|statistics| statistics.distance_m.to_string())),
+        optional(
+            statistics
+                .and_then(|s| s.duration)
+                .map(|d| d.num_seconds().to_string()),
+        ),
+        optional(
+            statistics
+                .and_then(|s| s.elevation_gain_m)
+                .map(|gain| gain.to_string()),
+        ),
+        optional(bounding_box.map(|b| b.bottom_left.latitude.to_string())),
+        optional(bounding_box.map(|b| b.bottom_left.longitude.to_string())),
+        optional(bounding_box.map(|b| b.top_right.latitude.to_string())),
+        optional(bounding_box.map(|b| b.top_right.longitude.to_string())),
+        csv_field(&optional(properties)),
+    ]
+    .join(",")
+}
+
+fn bounding_box(statistics: Option<&TrackStatistics>) -> Option<&SquaredFilter> {
+    statistics.and_then(|statistics| statistics.bounding_box.as_ref())
+}
+
+/// Quote the field when it contains a separator, a quote or a line break
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => String::from(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use crate::model::{Coordinate, PointKind, TrackPoint};
+
+    use super::*;
+
+    fn report() -> FilterReport {
+        FilterReport {
+            results: vec![FilterResult {
+                path: PathBuf::from("tracks/a, b.gpx"),
+                reason: MatchReason::FullScan,
+                first_matched_point: Some(Coordinate::new(49.5, 11.0)),
+                matched_kind: Some(PointKind::Route),
+                areas: vec![String::from("Nuremberg"), String::from("Fürth, city")],
+                statistics: Some(TrackStatistics {
+                    distance_m: 1500.0,
+                    duration: Some(chrono::Duration::seconds(600)),
+                    elevation_gain_m: None,
+                    bounding_box: None,
+                }),
+                properties: serde_json::from_str(r#"{"name": "Loop", "segments": 2}"#).ok(),
+                entries: Vec::new(),
+                elapsed: Duration::ZERO,
+            }],
+            summary: FilterSummary {
+                files_found: 3,
+                files_matched: 1,
+                ..Default::default()
+            },
+            errors: Vec::new(),
+        }
+    }
+
+    fn write(format: OutputFormat) -> String {
+        let mut output = Vec::new();
+        write_report(&mut output, &report(), format, true).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn write_text() {
+        assert_eq!(
+            "tracks/a, b.gpx: 1.50 km, 0:10:00 matched 'Nuremberg', 'Fürth, city'\n",
+            write(OutputFormat::Text)
+        );
+
+        let mut report = report();
+        report.results[0].statistics = None;
+        let mut output = Vec::new();
+        write_report(&mut output, &report, OutputFormat::Text, false).unwrap();
+        assert_eq!("tracks/a, b.gpx\n", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn write_csv() {
+        assert_eq!(
+            format!(
+                "{CSV_HEADER}\n{}{}{}\n",
+                "\"tracks/a, b.gpx\",full_scan,49.5,11,route,\"Nuremberg;Fürth, city\",1500,600,",
+                ",,,,,",
+                "\"{\"\"name\"\":\"\"Loop\"\",\"\"segments\"\":2}\""
+            ),
+            write(OutputFormat::Csv)
+        );
+    }
+
+    #[test]
+    fn write_json_and_ndjson() {
+        let value: Value = serde_json::from_str(&write(OutputFormat::Json)).unwrap();
+        assert_eq!(3, value["summary"]["files_found"]);
+        assert_eq!("full_scan", value["files"][0]["match"]);
+        assert_eq!(49.5, value["files"][0]["first_point"]["lat"]);
+        assert_eq!("route", value["files"][0]["first_point"]["kind"]);
+        assert_eq!(Value::Null, value["files"][0]["elevation_gain_m"]);
+        assert_eq!(2, value["files"][0]["properties"]["segments"]);
+
+        let ndjson = write(OutputFormat::Ndjson);
+        assert_eq!(1, ndjson.lines().count());
+        let line: Value = serde_json::from_str(ndjson.trim()).unwrap();
+        assert_eq!(value["files"][0], line);
+    }
+
+    #[test]
+    fn write_paths() {
+        assert_eq!("tracks/a, b.gpx\n", write(OutputFormat::Paths));
+    }
+
+    #[test]
+    fn write_sequence() {
+        let mut report = report();
+        let entered = |time: Option<&str>| TrackPoint {
+            time: time.and_then(|time| time.parse().ok()),
+            ..TrackPoint::new(Coordinate::new(49.5, 11.0), None)
+        };
+        report.results[0].entries = vec![entered(Some("2023-03-01T10:00:00Z")), entered(None)];
+
+        let mut output = Vec::new();
+        write_sequence_report(&mut output, &report, OutputFormat::Csv).unwrap();
+        assert_eq!(
+            "path,area,lat,lon,time\n\
+            \"tracks/a, b.gpx\",Nuremberg,49.5,11,2023-03-01T10:00:00+00:00\n\
+            \"tracks/a, b.gpx\",\"Fürth, city\",49.5,11,\n",
+            String::from_utf8(output).unwrap()
+        );
+
+        let mut output = Vec::new();
+        write_sequence_report(&mut output, &report, OutputFormat::Ndjson).unwrap();
+        let line: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!("Fürth, city", line["entries"][1]["area"]);
+        assert_eq!("Loop", line["properties"]["name"]);
+    }
+}
@@ -1,48 +1,357 @@
+use std::{io::BufRead, path::Path};
+
+use chrono::{DateTime, Utc};
+use quick_xml::{
+    events::{BytesStart, Event},
+    reader::Reader,
+};
 use regex::Regex;
 
-use crate::model::Coordinate;
+use crate::{
+    error::{xml_error, Error, Result},
+    model::{Coordinate, Gpx, Metadata, PointKind, Route, Track, TrackSegment, Waypoint},
+};
 
 /// Compiling a regex in first place seems to improve the performance by 20x
-/// in 200 files
-pub fn compile_coordinate_regex() -> Regex {
-    let pattern = r#"<trkpt\s+lat="(-?[\d.]+)"\s+lon="(-?[\d.]+)"\s*>"#;
-    Regex::new(pattern).expect("The patter is not a valid regex")
+/// in 200 files. Only the points of the given kinds are found, also as self-closing elements
+pub fn compile_coordinate_regex(kinds: &[PointKind]) -> Regex {
+    let elements: Vec<&str> = kinds.iter().map(PointKind::element).collect();
+    let pattern = format!(
+        r#"<({})\s+lat="(-?[\d.]+)"\s+lon="(-?[\d.]+)"\s*/?>"#,
+        elements.join("|")
+    );
+    Regex::new(&pattern).expect("The patter is not a valid regex")
 }
 
-/// The pre-filtering expects to find the trackpoints in a single line with just two parameters
-pub fn extract_first_coordinate_from_text(re: &Regex, text: &str) -> Option<Coordinate> {
+/// The pre-filtering expects to find each point in a single line with just two parameters. The
+/// points of the text are returned in order, each with its kind. The path is only used to report a
+/// number that can not be parsed
+pub fn extract_coordinates_from_text<'a>(
+    re: &'a Regex,
+    text: &'a str,
+    path: &'a Path,
+) -> impl Iterator<Item = Result<(Coordinate, PointKind)>> + 'a {
+    let parse = move |value: &str| {
+        value.parse::<f32>().map_err(|_| Error::InvalidValue {
+            path: path.to_path_buf(),
+            value: value.to_string(),
+        })
+    };
+
     // Use the regex to extract latitude and longitude
-    if let Some(captures) = re.captures(text) {
-        let latitude: f32 = captures[1].parse().unwrap();
-        let longitude: f32 = captures[2].parse().unwrap();
+    re.captures_iter(text).map(move |captures| {
+        let kind = PointKind::of_element(captures[1].as_bytes()).unwrap_or_default();
+        let latitude = parse(&captures[2])?;
+        let longitude = parse(&captures[3])?;
+        Ok((Coordinate::new(latitude, longitude), kind))
+    })
+}
+
+/// Parse the whole content of a .gpx file. Unknown elements are ignored and points without a valid
+/// `lat` and `lon` are skipped. The path is only used to report invalid XML
+pub fn parse_gpx<R: BufRead>(source: R, path: &Path) -> Result<Gpx> {
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut gpx = Gpx::default();
+    // Local names of the elements that are currently open
+    let mut open_elements: Vec<String> = Vec::new();
+    let mut point: Option<Waypoint> = None;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Err(e) => return Err(xml_error(path, &reader, e)),
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => {
+                let name = local_name(&e);
+                start_element(&mut gpx, &mut point, &name, &e);
+                open_elements.push(name);
+            }
+            Ok(Event::Empty(e)) => {
+                let name = local_name(&e);
+                start_element(&mut gpx, &mut point, &name, &e);
+                end_element(&mut gpx, &mut point, &name);
+            }
+            Ok(Event::End(_)) => {
+                if let Some(name) = open_elements.pop() {
+                    end_element(&mut gpx, &mut point, &name);
+                }
+            }
+            Ok(Event::Text(e)) => {
+                if let Ok(text) = e.unescape() {
+                    set_text(&mut gpx, &mut point, &open_elements, &text);
+                }
+            }
+            Ok(Event::CData(e)) => {
+                let text = String::from_utf8_lossy(&e);
+                set_text(&mut gpx, &mut point, &open_elements, &text);
+            }
+            _ => (), // ignore other xml events
+        }
+        buf.clear(); // clear memory
+    }
+    Ok(gpx)
+}
+
+pub(crate) fn local_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.local_name().as_ref()).into_owned()
+}
+
+/// Return the value of the attribute with the given name
+pub(crate) fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .filter_map(|a| a.ok())
+        .find(|attr| attr.key.as_ref() == name)
+        .map(|attr| String::from_utf8_lossy(&attr.value).into_owned())
+}
 
-        return Some(Coordinate::new(latitude, longitude));
+fn start_element(gpx: &mut Gpx, point: &mut Option<Waypoint>, name: &str, element: &BytesStart) {
+    match name {
+        "gpx" => gpx.creator = attribute(element, b"creator"),
+        "metadata" => gpx.metadata = Some(Metadata::default()),
+        "trk" => gpx.tracks.push(Track::default()),
+        "trkseg" => {
+            if let Some(track) = gpx.tracks.last_mut() {
+                track.segments.push(TrackSegment::default());
+            }
+        }
+        "rte" => gpx.routes.push(Route::default()),
+        "trkpt" | "rtept" | "wpt" => {
+            let latitude = attribute(element, b"lat").and_then(|lat| lat.parse().ok());
+            let longitude = attribute(element, b"lon").and_then(|lon| lon.parse().ok());
+            *point = match (latitude, longitude) {
+                (Some(latitude), Some(longitude)) => {
+                    Some(Waypoint::new(Coordinate::new(latitude, longitude)))
+                }
+                _ => None,
+            };
+        }
+        _ => (),
     }
-    None
+}
+
+fn end_element(gpx: &mut Gpx, point: &mut Option<Waypoint>, name: &str) {
+    let Some(finished) = (match name {
+        "trkpt" | "rtept" | "wpt" => point.take(),
+        _ => None,
+    }) else {
+        return;
+    };
+
+    match name {
+        "trkpt" => {
+            if let Some(segment) = gpx
+                .tracks
+                .last_mut()
+                .and_then(|track| track.segments.last_mut())
+            {
+                segment.points.push(finished);
+            }
+        }
+        "rtept" => {
+            if let Some(route) = gpx.routes.last_mut() {
+                route.points.push(finished);
+            }
+        }
+        _ => gpx.waypoints.push(finished),
+    }
+}
+
+/// Store the text of the innermost open element in the field it belongs to
+fn set_text(gpx: &mut Gpx, point: &mut Option<Waypoint>, open_elements: &[String], text: &str) {
+    let [.., parent, element] = open_elements else {
+        return;
+    };
+
+    if let Some(point) = point.as_mut() {
+        if open_elements.iter().any(|name| name == "extensions") {
+            point.extensions.insert(element.clone(), text.to_string());
+            return;
+        }
+        match element.as_str() {
+            "ele" => point.elevation = text.parse().ok(),
+            "time" => point.time = parse_time(text),
+            "hdop" => point.hdop = text.parse().ok(),
+            "name" => point.name = Some(text.to_string()),
+            _ => (),
+        }
+        return;
+    }
+
+    match (parent.as_str(), element.as_str()) {
+        ("metadata", element) => {
+            if let Some(metadata) = gpx.metadata.as_mut() {
+                match element {
+                    "name" => metadata.name = Some(text.to_string()),
+                    "desc" => metadata.description = Some(text.to_string()),
+                    "time" => metadata.time = parse_time(text),
+                    _ => (),
+                }
+            }
+        }
+        ("trk", element) => {
+            if let Some(track) = gpx.tracks.last_mut() {
+                match element {
+                    "name" => track.name = Some(text.to_string()),
+                    "desc" => track.description = Some(text.to_string()),
+                    "type" => track.activity_type = Some(text.to_string()),
+                    _ => (),
+                }
+            }
+        }
+        ("rte", element) => {
+            if let Some(route) = gpx.routes.last_mut() {
+                match element {
+                    "name" => route.name = Some(text.to_string()),
+                    "desc" => route.description = Some(text.to_string()),
+                    _ => (),
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Parse an ISO 8601 time as found in the `<time>` elements
+pub fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text.trim())
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn extract_first_coordinate_from_text(
+        re: &Regex,
+        text: &str,
+        path: &Path,
+    ) -> Result<Option<(Coordinate, PointKind)>> {
+        extract_coordinates_from_text(re, text, path)
+            .next()
+            .transpose()
+    }
+
     #[test]
     fn extract_text() {
         let input = r#"<trkpt lat="48.7890140" lon="9.2344190">"#;
         let coordinate = Coordinate::new(48.7890140, 9.2344190);
-        let re = compile_coordinate_regex();
+        let re = compile_coordinate_regex(&[PointKind::Track]);
         assert_eq!(
-            Some(coordinate),
-            extract_first_coordinate_from_text(&re, input)
+            Some((coordinate, PointKind::Track)),
+            extract_first_coordinate_from_text(&re, input, Path::new("test.gpx")).unwrap()
         );
     }
     #[test]
     fn extract_text_negative() {
         let input = r#"<trkpt lat="-48.7890140" lon="-9.2344190">"#;
         let coordinate = Coordinate::new(-48.7890140, -9.2344190);
-        let re = compile_coordinate_regex();
+        let re = compile_coordinate_regex(&[PointKind::Track]);
         assert_eq!(
-            Some(coordinate),
-            extract_first_coordinate_from_text(&re, input)
+            Some((coordinate, PointKind::Track)),
+            extract_first_coordinate_from_text(&re, input, Path::new("test.gpx")).unwrap()
         );
     }
+
+    #[test]
+    fn extract_text_of_point_kinds() {
+        let input = r#"<wpt lat="49.45" lon="11.07"/><rtept lat="49.46" lon="11.08"/>"#;
+        let extract = |kinds: &[PointKind]| {
+            let re = compile_coordinate_regex(kinds);
+            extract_first_coordinate_from_text(&re, input, Path::new("test.gpx")).unwrap()
+        };
+        assert_eq!(None, extract(&[PointKind::Track]));
+        assert_eq!(
+            Some((Coordinate::new(49.46, 11.08), PointKind::Route)),
+            extract(&[PointKind::Track, PointKind::Route])
+        );
+        assert_eq!(
+            Some((Coordinate::new(49.45, 11.07), PointKind::Waypoint)),
+            extract(&[PointKind::Route, PointKind::Waypoint])
+        );
+
+        let re = compile_coordinate_regex(&[PointKind::Route, PointKind::Waypoint]);
+        let kinds: Vec<PointKind> =
+            extract_coordinates_from_text(&re, input, Path::new("test.gpx"))
+                .map(|point| point.unwrap().1)
+                .collect();
+        assert_eq!(vec![PointKind::Waypoint, PointKind::Route], kinds);
+    }
+
+    #[test]
+    fn extract_text_invalid_number() {
+        let input = r#"<trkpt lat="48.78.90140" lon="9.2344190">"#;
+        let re = compile_coordinate_regex(&[PointKind::Track]);
+        assert!(matches!(
+            extract_first_coordinate_from_text(&re, input, Path::new("test.gpx")),
+            Err(Error::InvalidValue { value, .. }) if value == "48.78.90140"
+        ));
+    }
+
+    #[test]
+    fn parse_full_gpx() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx creator="Test" version="1.1" xmlns="http://www.topografix.com/GPX/1/1"
+    xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+ <metadata>
+  <name>Weekend</name>
+  <time>2023-03-03T08:43:59Z</time>
+ </metadata>
+ <wpt lat="49.45" lon="11.07"><name>Castle</name></wpt>
+ <rte>
+  <name>Plan</name>
+  <rtept lat="49.46" lon="11.08"/>
+  <rtept lat="49.47" lon="11.09"/>
+ </rte>
+ <trk>
+  <name>Morning Walk</name>
+  <desc><![CDATA[Around <the> park]]></desc>
+  <type>10</type>
+  <trkseg>
+   <trkpt lat="49.4798870" lon="10.9854730">
+    <ele>291.9</ele>
+    <time>2023-03-03T08:43:59Z</time>
+    <hdop>1.5</hdop>
+    <extensions>
+     <gpxtpx:TrackPointExtension><gpxtpx:hr>140</gpxtpx:hr></gpxtpx:TrackPointExtension>
+    </extensions>
+   </trkpt>
+  </trkseg>
+  <trkseg>
+   <trkpt lat="49.4798500" lon="10.9855780"/>
+   <trkpt lat="invalid" lon="10.9855780"/>
+  </trkseg>
+ </trk>
+</gpx>"#;
+        let gpx = parse_gpx(input.as_bytes(), Path::new("full.gpx")).unwrap();
+
+        assert_eq!(Some(String::from("Test")), gpx.creator);
+        let metadata = gpx.metadata.as_ref().unwrap();
+        assert_eq!(Some(String::from("Weekend")), metadata.name);
+        assert_eq!(parse_time("2023-03-03T08:43:59Z"), metadata.time);
+
+        assert_eq!(1, gpx.waypoints.len());
+        assert_eq!(Some(String::from("Castle")), gpx.waypoints[0].name);
+
+        assert_eq!(1, gpx.routes.len());
+        assert_eq!(Some(String::from("Plan")), gpx.routes[0].name);
+        assert_eq!(2, gpx.routes[0].points.len());
+
+        let track = &gpx.tracks[0];
+        assert_eq!(Some(String::from("Morning Walk")), track.name);
+        assert_eq!(Some(String::from("Around <the> park")), track.description);
+        assert_eq!(Some(String::from("10")), track.activity_type);
+        assert_eq!(2, track.segments.len());
+        assert_eq!(1, track.segments[1].points.len());
+
+        let point = &track.segments[0].points[0];
+        assert_eq!(Coordinate::new(49.4798870, 10.9854730), point.coordinate);
+        assert_eq!(Some(291.9), point.elevation);
+        assert_eq!(parse_time("2023-03-03T08:43:59Z"), point.time);
+        assert_eq!(Some(1.5), point.hdop);
+        assert_eq!(Some(&String::from("140")), point.extensions.get("hr"));
+        assert_eq!(None, point.name);
+    }
 }
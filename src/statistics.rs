@@ -0,0 +1,132 @@
+use crate::{
+    geodesy::haversine_distance_in_m,
+    model::{Coordinate, SquaredFilter, TrackPoint, TrackStatistics},
+};
+
+/// Compute the distance, duration, elevation gain and bounding box of the track. The distance and
+/// elevation gain are only measured along each line, never from the end of a line to the start of
+/// the next one or to a waypoint
+pub fn compute_statistics(points: &[TrackPoint]) -> TrackStatistics {
+    let mut statistics = TrackStatistics::default();
+
+    for line in points.chunk_by(|a, b| a.line == b.line) {
+        for segment in line.windows(2) {
+            statistics.distance_m +=
+                haversine_distance_in_m(&segment[0].coordinate, &segment[1].coordinate);
+        }
+
+        let elevations: Vec<f64> = line.iter().filter_map(|point| point.elevation).collect();
+        if !elevations.is_empty() {
+            let gain: f64 = elevations
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).max(0.0))
+                .sum();
+            *statistics.elevation_gain_m.get_or_insert(0.0) += gain;
+        }
+    }
+
+    let first_time = points.iter().find_map(|point| point.time);
+    let last_time = points.iter().rev().find_map(|point| point.time);
+    if let (Some(first_time), Some(last_time)) = (first_time, last_time) {
+        statistics.duration = Some(last_time - first_time);
+    }
+
+    statistics.bounding_box = bounding_box(points);
+    statistics
+}
+
+/// Smallest box containing all the points, without wrapping around the antimeridian
+fn bounding_box(points: &[TrackPoint]) -> Option<SquaredFilter> {
+    let first = points.first()?.coordinate;
+    let (mut south_west, mut north_east) = (first, first);
+
+    for point in points {
+        let coordinate = point.coordinate;
+        south_west.latitude = south_west.latitude.min(coordinate.latitude);
+        south_west.longitude = south_west.longitude.min(coordinate.longitude);
+        north_east.latitude = north_east.latitude.max(coordinate.latitude);
+        north_east.longitude = north_east.longitude.max(coordinate.longitude);
+    }
+
+    Some(SquaredFilter::new(
+        Coordinate::new(south_west.latitude, south_west.longitude),
+        Coordinate::new(north_east.latitude, north_east.longitude),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Utc};
+
+    use super::*;
+
+    #[test]
+    fn statistics_of_track() {
+        let start: DateTime<Utc> = "2023-03-03T08:00:00Z".parse().unwrap();
+        let point = |latitude: f32, longitude: f32, minutes: i64, elevation: f64| TrackPoint {
+            elevation: Some(elevation),
+            ..TrackPoint::new(
+                Coordinate::new(latitude, longitude),
+                Some(start + Duration::minutes(minutes)),
+            )
+        };
+        let track = vec![
+            point(49.0, 11.0, 0, 300.0),
+            point(49.1, 11.0, 10, 320.0),
+            point(49.1, 11.2, 25, 310.0),
+            point(48.9, 11.1, 45, 330.0),
+        ];
+
+        let statistics = compute_statistics(&track);
+
+        let expected_m: f64 = track
+            .windows(2)
+            .map(|pair| haversine_distance_in_m(&pair[0].coordinate, &pair[1].coordinate))
+            .sum();
+        assert_eq!(expected_m, statistics.distance_m);
+        assert_eq!(Some(Duration::minutes(45)), statistics.duration);
+        assert_eq!(Some(40.0), statistics.elevation_gain_m);
+        assert_eq!(
+            Some(SquaredFilter::new(
+                Coordinate::new(48.9, 11.0),
+                Coordinate::new(49.1, 11.2)
+            )),
+            statistics.bounding_box
+        );
+    }
+
+    #[test]
+    fn statistics_of_several_lines() {
+        let point = |latitude: f32, line: usize, elevation: f64| TrackPoint {
+            elevation: Some(elevation),
+            line,
+            ..TrackPoint::new(Coordinate::new(latitude, 11.0), None)
+        };
+        let track = vec![
+            point(49.0, 0, 300.0),
+            point(49.1, 0, 320.0),
+            point(48.0, 1, 100.0),
+            point(48.1, 1, 110.0),
+        ];
+
+        let statistics = compute_statistics(&track);
+
+        let expected_m = haversine_distance_in_m(&track[0].coordinate, &track[1].coordinate)
+            + haversine_distance_in_m(&track[2].coordinate, &track[3].coordinate);
+        assert_eq!(expected_m, statistics.distance_m);
+        assert_eq!(Some(30.0), statistics.elevation_gain_m);
+    }
+
+    #[test]
+    fn statistics_without_time_or_elevation() {
+        let track = vec![TrackPoint::new(Coordinate::new(49.0, 11.0), None)];
+
+        let statistics = compute_statistics(&track);
+
+        assert_eq!(0.0, statistics.distance_m);
+        assert_eq!(None, statistics.duration);
+        assert_eq!(None, statistics.elevation_gain_m);
+        assert!(statistics.bounding_box.is_some());
+        assert_eq!(TrackStatistics::default(), compute_statistics(&[]));
+    }
+}
@@ -0,0 +1,178 @@
+use std::{io::BufRead, path::Path};
+
+use quick_xml::{events::Event, reader::Reader};
+
+use crate::{
+    error::{xml_error, Result},
+    model::{ActivityType, Coordinate, TrackHeader, TrackPoint},
+    parser::{attribute, local_name, parse_time},
+};
+
+/// Read the `<Trackpoint>` elements of a .tcx activity or course that have a `<Position>`, with
+/// their `<Time>` and `<AltitudeMeters>`
+pub fn read_tcx_track_points<R: BufRead>(source: R, path: &Path) -> Result<Vec<TrackPoint>> {
+    read_points(source, path, false)
+}
+
+/// Read the .tcx file only until the first point with a position
+pub fn read_first_tcx_coordinate<R: BufRead>(source: R, path: &Path) -> Result<Option<Coordinate>> {
+    let points = read_points(source, path, true)?;
+    Ok(points.first().map(|point| point.coordinate))
+}
+
+fn read_points<R: BufRead>(source: R, path: &Path, first_only: bool) -> Result<Vec<TrackPoint>> {
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut points: Vec<TrackPoint> = Vec::new();
+    // Local names of the elements that are currently open
+    let mut open_elements: Vec<String> = Vec::new();
+    let (mut latitude, mut longitude): (Option<f32>, Option<f32>) = (None, None);
+    let mut time = None;
+    let mut elevation = None;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Err(e) => return Err(xml_error(path, &reader, e)),
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => {
+                let name = local_name(&e);
+                if name == "Trackpoint" {
+                    (latitude, longitude, time, elevation) = (None, None, None, None);
+                }
+                open_elements.push(name);
+            }
+            Ok(Event::End(_)) => {
+                let closed = open_elements.pop();
+                // Points without position, e.g. only with the heart rate, are skipped
+                if let (Some("Trackpoint"), Some(latitude), Some(longitude)) =
+                    (closed.as_deref(), latitude, longitude)
+                {
+                    points.push(TrackPoint {
+                        elevation,
+                        ..TrackPoint::new(Coordinate::new(latitude, longitude), time)
+                    });
+                    if first_only {
+                        break;
+                    }
+                }
+            }
+            Ok(Event::Text(e)) => {
+                if let ([.., parent, element], Ok(text)) = (&open_elements[..], e.unescape()) {
+                    match (parent.as_str(), element.as_str()) {
+                        ("Position", "LatitudeDegrees") => latitude = text.trim().parse().ok(),
+                        ("Position", "LongitudeDegrees") => longitude = text.trim().parse().ok(),
+                        ("Trackpoint", "Time") => time = parse_time(&text),
+                        ("Trackpoint", "AltitudeMeters") => elevation = text.trim().parse().ok(),
+                        _ => (),
+                    }
+                }
+            }
+            _ => (), // ignore other xml events
+        }
+        buf.clear(); // clear memory
+    }
+    Ok(points)
+}
+
+/// Read the sport, start time and name of the course. The file is only read until the end of the
+/// first point. The start time is the `<Id>` of the activity, the start of the first lap or
+/// otherwise the time of the first point
+pub fn read_tcx_track_header<R: BufRead>(source: R, path: &Path) -> Result<TrackHeader> {
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut header = TrackHeader::default();
+    let mut open_elements: Vec<String> = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Err(e) => return Err(xml_error(path, &reader, e)),
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => {
+                let name = local_name(&e);
+                match name.as_str() {
+                    "Activity" => {
+                        header.activity_type =
+                            attribute(&e, b"Sport").map(|sport| ActivityType::parse(&sport))
+                    }
+                    "Lap" if header.start_time.is_none() => {
+                        header.start_time =
+                            attribute(&e, b"StartTime").and_then(|time| parse_time(&time))
+                    }
+                    _ => (),
+                }
+                open_elements.push(name);
+            }
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"Trackpoint" => break,
+            Ok(Event::End(_)) => {
+                open_elements.pop();
+            }
+            Ok(Event::Text(e)) => {
+                if let ([.., parent, element], Ok(text)) = (&open_elements[..], e.unescape()) {
+                    match (parent.as_str(), element.as_str()) {
+                        ("Activity", "Id") | ("Trackpoint", "Time")
+                            if header.start_time.is_none() =>
+                        {
+                            header.start_time = parse_time(&text)
+                        }
+                        ("Course", "Name") => header.name = Some(text.into_owned()),
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FILE: &str = "test/files/formats/ride.tcx";
+
+    #[test]
+    fn read_activity() {
+        let tcx = std::fs::read(TEST_FILE).unwrap();
+        let path = Path::new(TEST_FILE);
+        let points = read_tcx_track_points(&tcx[..], path).unwrap();
+
+        assert_eq!(2, points.len());
+        assert_eq!(Coordinate::new(49.479887, 10.985473), points[0].coordinate);
+        assert_eq!(Some(310.4), points[0].elevation);
+        assert_eq!(
+            "2023-03-03T08:54:05+00:00",
+            points[1].time.unwrap().to_rfc3339()
+        );
+        assert_eq!(None, points[1].elevation);
+
+        let first = read_first_tcx_coordinate(&tcx[..], path).unwrap();
+        assert_eq!(Some(points[0].coordinate), first);
+    }
+
+    #[test]
+    fn read_header() {
+        let tcx = std::fs::read(TEST_FILE).unwrap();
+        let header = read_tcx_track_header(&tcx[..], Path::new(TEST_FILE)).unwrap();
+
+        assert_eq!(Some(ActivityType::Ride), header.activity_type);
+        assert_eq!(
+            "2023-03-03T08:43:59+00:00",
+            header.start_time.unwrap().to_rfc3339()
+        );
+        assert_eq!(None, header.name);
+
+        let course = "<TrainingCenterDatabase><Courses><Course><Name>Loop</Name><Track>\
+            <Trackpoint><Time>2023-05-01T10:00:00Z</Time></Trackpoint></Track></Course>\
+            </Courses></TrainingCenterDatabase>";
+        let header = read_tcx_track_header(course.as_bytes(), Path::new("loop.tcx")).unwrap();
+        assert_eq!(Some(String::from("Loop")), header.name);
+        assert_eq!(
+            "2023-05-01T10:00:00+00:00",
+            header.start_time.unwrap().to_rfc3339()
+        );
+    }
+}
@@ -1,122 +1,637 @@
-use crate::model::{Coordinate, SquaredFilter};
+use chrono::Duration;
 
+use crate::{
+    geodesy::{
+        haversine_distance_in_m, point_to_box_distance_in_m, point_to_segment_distance_in_m,
+        vincenty_distance_in_m,
+    },
+    model::{
+        AreaFilter, AreaVisit, CircleFilter, Coordinate, CorridorFilter, PolygonFilter,
+        SquaredFilter, TrackPoint,
+    },
+};
+
+/// The distance in km is measured to the bounding box of the area, so a point is never discarded
+/// if it is closer than `distance` to the area itself. Every point is far from an empty area
 pub fn is_point_more_than_x_distance_from_filter(
-    area: &SquaredFilter,
+    area: &AreaFilter,
     point: &Coordinate,
     distance: f32,
 ) -> bool {
-    let closest_longitude = f32::max(
-        area.top_left.longitude,
-        f32::min(point.longitude, area.top_right.longitude),
-    );
-    let closest_latitude = f32::max(
-        area.top_left.latitude,
-        f32::min(point.latitude, area.bottom_right.latitude),
-    );
+    let Some(area) = area.bounding_box() else {
+        return true;
+    };
 
-    let closest_point = Coordinate::new(closest_longitude, closest_latitude);
+    point_to_box_distance_in_m(point, &area) / 1000.0 > distance as f64
+}
 
-    match calculate_distance_between_two_coordinates_in_km(&point, &closest_point) > distance {
-        true => return true,
-        false => return false,
+/// Check if the provided point is within the boundaries of the area
+pub fn is_point_in_area(area: &AreaFilter, point: &Coordinate) -> bool {
+    match area {
+        AreaFilter::Squared(area) => is_point_in_squared_filter(area, point),
+        AreaFilter::Polygon(polygon) => is_point_in_polygon(polygon, point),
+        AreaFilter::MultiPolygon(polygons) => polygons
+            .iter()
+            .any(|polygon| is_point_in_polygon(polygon, point)),
+        AreaFilter::Circle(circle) => is_point_in_circle(circle, point),
+        AreaFilter::Corridor(corridor) => is_point_in_corridor(corridor, point),
     }
 }
 
-/// Check if the provided point is within the boundaries of the area
-pub fn is_point_in_area(area: &SquaredFilter, point: &Coordinate) -> bool {
-    point.longitude >= area.top_left.longitude
-        && point.longitude < area.top_right.longitude
+/// Check if the straight line between the two consecutive points `a` and `b` of a track touches
+/// the area. Segments that go over the antimeridian are split in two
+pub fn is_segment_in_area(area: &AreaFilter, a: &Coordinate, b: &Coordinate) -> bool {
+    if is_point_in_area(area, a) || is_point_in_area(area, b) {
+        return true;
+    }
+
+    split_at_antimeridian(a, b).iter().any(|(a, b)| match area {
+        AreaFilter::Squared(area) => segment_crosses_squared_filter(area, a, b),
+        AreaFilter::Polygon(polygon) => segment_crosses_polygon(polygon, a, b),
+        AreaFilter::MultiPolygon(polygons) => polygons
+            .iter()
+            .any(|polygon| segment_crosses_polygon(polygon, a, b)),
+        AreaFilter::Circle(circle) => {
+            point_to_segment_distance_in_m(&circle.center, a, b) <= circle.radius_m
+        }
+        AreaFilter::Corridor(corridor) => segment_crosses_corridor(corridor, a, b),
+    })
+}
+
+/// Percentage of the length of the track that is inside the area. A segment between two points
+/// of the same line counts fully when both ends are inside and half when only one of them is. A
+/// track without length is either fully in or out depending on its first point
+pub fn percentage_of_track_in_area(area: &AreaFilter, points: &[TrackPoint]) -> f32 {
+    let inside: Vec<bool> = points
+        .iter()
+        .map(|point| is_point_in_area(area, &point.coordinate))
+        .collect();
+
+    let mut total_length = 0.0;
+    let mut length_inside = 0.0;
+    for (index, segment) in points.windows(2).enumerate() {
+        if segment[0].line != segment[1].line {
+            continue;
+        }
+        let length = haversine_distance_in_m(&segment[0].coordinate, &segment[1].coordinate);
+        total_length += length;
+        match (inside[index], inside[index + 1]) {
+            (true, true) => length_inside += length,
+            (true, false) | (false, true) => length_inside += length / 2.0,
+            (false, false) => (),
+        }
+    }
+
+    if total_length == 0.0 {
+        return match inside.first() {
+            Some(true) => 100.0,
+            _ => 0.0,
+        };
+    }
+    (length_inside / total_length * 100.0) as f32
+}
+
+/// Time and distance of the track inside the area. As for the percentage, a segment between two
+/// points of the same line counts fully when both ends are inside and half when only one of them
+/// is. Segments without the time of both points do not add to the duration
+pub fn visit_in_area(area: &AreaFilter, points: &[TrackPoint]) -> AreaVisit {
+    let inside: Vec<bool> = points
+        .iter()
+        .map(|point| is_point_in_area(area, &point.coordinate))
+        .collect();
+
+    let mut duration = Duration::zero();
+    let mut distance_m = 0.0;
+    for (index, segment) in points.windows(2).enumerate() {
+        if segment[0].line != segment[1].line {
+            continue;
+        }
+        let share = match (inside[index], inside[index + 1]) {
+            (true, true) => 2,
+            (true, false) | (false, true) => 1,
+            (false, false) => continue,
+        };
+
+        distance_m += haversine_distance_in_m(&segment[0].coordinate, &segment[1].coordinate)
+            * share as f64
+            / 2.0;
+        if let (Some(start), Some(end)) = (segment[0].time, segment[1].time) {
+            duration += (end - start) * share / 2;
+        }
+    }
+
+    AreaVisit {
+        duration,
+        distance_m,
+    }
+}
+
+/// The distance to the center is measured over the surface of the earth
+fn is_point_in_circle(circle: &CircleFilter, point: &Coordinate) -> bool {
+    vincenty_distance_in_m(&circle.center, point) <= circle.radius_m
+}
+
+/// Check if the provided point is within the boundaries of the box, which may wrap around the
+/// antimeridian
+fn is_point_in_squared_filter(area: &SquaredFilter, point: &Coordinate) -> bool {
+    area.contains_longitude(point.longitude)
         && point.latitude < area.top_left.latitude
         && point.latitude >= area.bottom_left.latitude
 }
 
-/// Constants are extracted from:
-/// https://stackoverflow.com/questions/1253499/simple-calculations-for-working-with-lat-lon-and-km-distance
-fn calculate_distance_between_two_coordinates_in_km(a: &Coordinate, b: &Coordinate) -> f32 {
-    const LAT_TO_KM: f32 = 110.574;
-    const LON_TO_KM: f32 = 111.320;
+/// The point must be closer than the buffer to any of the segments of the route
+fn is_point_in_corridor(corridor: &CorridorFilter, point: &Coordinate) -> bool {
+    match corridor.route.as_slice() {
+        [] => false,
+        [single] => haversine_distance_in_m(single, point) <= corridor.buffer_m,
+        route => route.windows(2).any(|segment| {
+            point_to_segment_distance_in_m(point, &segment[0], &segment[1]) <= corridor.buffer_m
+        }),
+    }
+}
 
-    let delta_latitude_km = (a.latitude - b.latitude) * LAT_TO_KM;
-    let delta_longitude_km =
-        (a.longitude - b.longitude) * LON_TO_KM * a.latitude.to_radians().cos();
+/// Both ends of the segment are outside, so it can only touch the box by crossing one of its edges
+fn segment_crosses_squared_filter(area: &SquaredFilter, a: &Coordinate, b: &Coordinate) -> bool {
+    let south = area.bottom_left.latitude;
+    let north = area.top_right.latitude;
+    let west = area.bottom_left.longitude;
+    let east = area.top_right.longitude;
 
-    let distance_km = (delta_latitude_km.powi(2) + delta_longitude_km.powi(2)).sqrt();
-    distance_km
+    let boxes = match area.crosses_antimeridian() {
+        true => vec![(west, 180.0), (-180.0, east)],
+        false => vec![(west, east)],
+    };
+
+    boxes.iter().any(|(west, east)| {
+        let south_west = Coordinate::new(south, *west);
+        let south_east = Coordinate::new(south, *east);
+        let north_east = Coordinate::new(north, *east);
+        let north_west = Coordinate::new(north, *west);
+        let ring = [south_west, south_east, north_east, north_west];
+        segment_crosses_ring(&ring, a, b)
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Both ends of the segment are outside the polygon, so it can only touch it by crossing the
+/// exterior ring or the ring of a hole
+fn segment_crosses_polygon(polygon: &PolygonFilter, a: &Coordinate, b: &Coordinate) -> bool {
+    segment_crosses_ring(&polygon.exterior, a, b)
+        || polygon
+            .holes
+            .iter()
+            .any(|hole| segment_crosses_ring(hole, a, b))
+}
 
-    #[test]
-    fn distance_between_two_points_in_km() {
-        let coordinate_a = Coordinate::new(20.5763675, 47.3753910);
-        let coordinate_b = Coordinate::new(20.5763675, 87.3753910);
+/// The segment is in the corridor if it crosses the route or one of the ends of either segment
+/// is closer than the buffer to the other segment
+fn segment_crosses_corridor(corridor: &CorridorFilter, a: &Coordinate, b: &Coordinate) -> bool {
+    corridor.route.windows(2).any(|route| {
+        segments_intersect(a, b, &route[0], &route[1])
+            || point_to_segment_distance_in_m(&route[0], a, b) <= corridor.buffer_m
+            || point_to_segment_distance_in_m(&route[1], a, b) <= corridor.buffer_m
+    })
+}
 
-        assert_eq!(
-            4168.7314,
-            calculate_distance_between_two_coordinates_in_km(&coordinate_a, &coordinate_b)
-        );
-        assert_ne!(
-            4168.7310,
-            calculate_distance_between_two_coordinates_in_km(&coordinate_a, &coordinate_b)
-        );
+/// Check if the segment crosses any of the edges of the ring
+fn segment_crosses_ring(ring: &[Coordinate], a: &Coordinate, b: &Coordinate) -> bool {
+    if ring.is_empty() {
+        return false;
+    }
+
+    let mut previous = ring[ring.len() - 1];
+    for current in ring {
+        if segments_intersect(a, b, &previous, current) {
+            return true;
+        }
+        previous = *current;
     }
+    false
+}
+
+/// Check if the segments `a`-`b` and `c`-`d` intersect, treating latitude and longitude as plane
+/// coordinates
+fn segments_intersect(a: &Coordinate, b: &Coordinate, c: &Coordinate, d: &Coordinate) -> bool {
+    let orientation = |p: &Coordinate, q: &Coordinate, r: &Coordinate| -> f32 {
+        (q.longitude - p.longitude) * (r.latitude - p.latitude)
+            - (q.latitude - p.latitude) * (r.longitude - p.longitude)
+    };
+    let on_segment = |p: &Coordinate, q: &Coordinate, r: &Coordinate| -> bool {
+        r.longitude >= f32::min(p.longitude, q.longitude)
+            && r.longitude <= f32::max(p.longitude, q.longitude)
+            && r.latitude >= f32::min(p.latitude, q.latitude)
+            && r.latitude <= f32::max(p.latitude, q.latitude)
+    };
+
+    let d1 = orientation(c, d, a);
+    let d2 = orientation(c, d, b);
+    let d3 = orientation(a, b, c);
+    let d4 = orientation(a, b, d);
+
+    if ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0))
+        && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+    {
+        return true;
+    }
+
+    (d1 == 0.0 && on_segment(c, d, a))
+        || (d2 == 0.0 && on_segment(c, d, b))
+        || (d3 == 0.0 && on_segment(a, b, c))
+        || (d4 == 0.0 && on_segment(a, b, d))
+}
+
+/// A segment whose ends are more than 180 degrees of longitude apart takes the short way over the
+/// antimeridian, so it is split in two segments ending at 180 and -180
+fn split_at_antimeridian(a: &Coordinate, b: &Coordinate) -> Vec<(Coordinate, Coordinate)> {
+    let delta_longitude = b.longitude - a.longitude;
+    if delta_longitude.abs() <= 180.0 {
+        return vec![(*a, *b)];
+    }
+
+    let (edge_a, unwrapped_b) = match delta_longitude > 0.0 {
+        true => (-180.0, b.longitude - 360.0),
+        false => (180.0, b.longitude + 360.0),
+    };
+    let t = (edge_a - a.longitude) / (unwrapped_b - a.longitude);
+    let latitude = a.latitude + t * (b.latitude - a.latitude);
+
+    vec![
+        (*a, Coordinate::new(latitude, edge_a)),
+        (Coordinate::new(latitude, -edge_a), *b),
+    ]
+}
+
+/// A point is in the polygon when it is inside the exterior ring and outside all the holes
+fn is_point_in_polygon(polygon: &PolygonFilter, point: &Coordinate) -> bool {
+    is_point_in_ring(&polygon.exterior, point)
+        && !polygon
+            .holes
+            .iter()
+            .any(|hole| is_point_in_ring(hole, point))
+}
+
+/// Ray casting (even-odd rule): count how many edges of the ring are crossed by a ray going east
+/// from the point
+fn is_point_in_ring(ring: &[Coordinate], point: &Coordinate) -> bool {
+    if ring.len() < 3 {
+        return false;
+    }
+
+    let mut inside = false;
+    let mut previous = ring[ring.len() - 1];
+    for current in ring {
+        if (current.latitude > point.latitude) != (previous.latitude > point.latitude) {
+            let crossing_longitude = current.longitude
+                + (point.latitude - current.latitude) * (previous.longitude - current.longitude)
+                    / (previous.latitude - current.latitude);
+            if point.longitude < crossing_longitude {
+                inside = !inside;
+            }
+        }
+        previous = *current;
+    }
+    inside
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
 
     #[test]
     fn distance_to_area_same_axis_x() {
-        let point = Coordinate::new(80.0, 100.0);
-        let area = SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(80.0, 80.0));
+        // 10 degrees east of the box, around 712.6 km along the great circle
+        let point = Coordinate::new(50.0, 70.0);
+        let area =
+            SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(60.0, 60.0)).into();
 
         assert_eq!(
             false,
-            is_point_more_than_x_distance_from_filter(&area, &point, 387.0)
+            is_point_more_than_x_distance_from_filter(&area, &point, 713.0)
         );
         assert_eq!(
             true,
-            is_point_more_than_x_distance_from_filter(&area, &point, 386.0)
+            is_point_more_than_x_distance_from_filter(&area, &point, 712.0)
         );
     }
     #[test]
     fn distance_to_area_same_axis_y() {
-        let point = Coordinate::new(100.0, 80.0);
-        let area = SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(80.0, 80.0));
+        // 10 degrees north of the box, around 1112.0 km along the meridian
+        let point = Coordinate::new(70.0, 50.0);
+        let area =
+            SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(60.0, 60.0)).into();
 
         assert_eq!(
             false,
-            is_point_more_than_x_distance_from_filter(&area, &point, 2212.0)
+            is_point_more_than_x_distance_from_filter(&area, &point, 1112.0)
         );
         assert_eq!(
             true,
-            is_point_more_than_x_distance_from_filter(&area, &point, 2211.0)
+            is_point_more_than_x_distance_from_filter(&area, &point, 1111.0)
         );
     }
     #[test]
     fn distance_to_area_middle() {
-        let point = Coordinate::new(90.0, 90.0);
-        let area = SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(80.0, 80.0));
+        // The closest point is the north-east corner, around 1203.5 km away
+        let point = Coordinate::new(70.0, 70.0);
+        let area =
+            SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(60.0, 60.0)).into();
 
         assert_eq!(
             false,
-            is_point_more_than_x_distance_from_filter(&area, &point, 1106.0)
+            is_point_more_than_x_distance_from_filter(&area, &point, 1204.0)
         );
         assert_eq!(
             true,
-            is_point_more_than_x_distance_from_filter(&area, &point, 1105.0)
+            is_point_more_than_x_distance_from_filter(&area, &point, 1203.0)
         );
     }
     #[test]
+    fn distance_to_area_across_antimeridian() {
+        // 1 degree east of the east edge at -178, on the other side of the antimeridian
+        let point = Coordinate::new(-15.0, -177.0);
+        let area = SquaredFilter::new(
+            Coordinate::new(-21.0, 176.0),
+            Coordinate::new(-12.0, -178.0),
+        )
+        .into();
+
+        assert!(!is_point_more_than_x_distance_from_filter(
+            &area, &point, 108.0
+        ));
+        assert!(is_point_more_than_x_distance_from_filter(
+            &area, &point, 107.0
+        ));
+        assert!(!is_point_more_than_x_distance_from_filter(
+            &area,
+            &Coordinate::new(-15.0, 179.0),
+            0.0
+        ));
+    }
+    #[test]
+    fn distance_to_area_is_not_swapped() {
+        // Far from the box, but close to a box with latitude and longitude swapped
+        let point = Coordinate::new(10.0, 50.0);
+        let area =
+            SquaredFilter::new(Coordinate::new(45.0, 5.0), Coordinate::new(55.0, 15.0)).into();
+
+        assert!(is_point_more_than_x_distance_from_filter(
+            &area, &point, 3000.0
+        ));
+    }
+    #[test]
     fn point_is_in_area() {
         let point = Coordinate::new(45.0, 45.0);
-        let area = SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(80.0, 80.0));
+        let area =
+            SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(80.0, 80.0)).into();
 
         assert_eq!(true, is_point_in_area(&area, &point));
     }
     #[test]
+    fn point_is_in_area_across_antimeridian() {
+        let area = SquaredFilter::new(
+            Coordinate::new(-21.0, 176.0),
+            Coordinate::new(-12.0, -178.0),
+        )
+        .into();
+
+        assert!(is_point_in_area(&area, &Coordinate::new(-17.7, 178.0)));
+        assert!(is_point_in_area(&area, &Coordinate::new(-16.0, -179.5)));
+        assert!(!is_point_in_area(&area, &Coordinate::new(-17.7, 0.0)));
+        assert!(!is_point_in_area(&area, &Coordinate::new(-17.7, -170.0)));
+    }
+    #[test]
     fn point_is_not_in_area() {
         let point = Coordinate::new(91.0, 91.0);
-        let area = SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(80.0, 80.0));
+        let area =
+            SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(80.0, 80.0)).into();
 
         assert_eq!(false, is_point_in_area(&area, &point));
     }
+
+    fn polygon_with_hole() -> AreaFilter {
+        let exterior = vec![
+            Coordinate::new(40.0, 40.0),
+            Coordinate::new(40.0, 80.0),
+            Coordinate::new(80.0, 60.0),
+            Coordinate::new(40.0, 40.0),
+        ];
+        let hole = vec![
+            Coordinate::new(45.0, 55.0),
+            Coordinate::new(45.0, 65.0),
+            Coordinate::new(55.0, 60.0),
+        ];
+        PolygonFilter::new(exterior, vec![hole]).into()
+    }
+
+    #[test]
+    fn point_is_in_polygon() {
+        let area = polygon_with_hole();
+
+        assert!(is_point_in_area(&area, &Coordinate::new(42.0, 60.0)));
+        assert!(is_point_in_area(&area, &Coordinate::new(60.0, 60.0)));
+    }
+
+    #[test]
+    fn point_is_not_in_polygon() {
+        let area = polygon_with_hole();
+
+        // Inside the bounding box but outside the triangle
+        assert!(!is_point_in_area(&area, &Coordinate::new(70.0, 45.0)));
+        // Inside the hole
+        assert!(!is_point_in_area(&area, &Coordinate::new(48.0, 60.0)));
+    }
+
+    #[test]
+    fn point_is_in_circle() {
+        let area = CircleFilter::new(Coordinate::new(49.4798870, 10.9854730), 500.0)
+            .unwrap()
+            .into();
+
+        // Around 440 m to the north
+        assert!(is_point_in_area(
+            &area,
+            &Coordinate::new(49.4838, 10.9854730)
+        ));
+        // Around 560 m to the north
+        assert!(!is_point_in_area(
+            &area,
+            &Coordinate::new(49.4849, 10.9854730)
+        ));
+    }
+
+    #[test]
+    fn point_is_in_corridor() {
+        let route = vec![
+            Coordinate::new(0.0, 0.0),
+            Coordinate::new(0.0, 0.01),
+            Coordinate::new(0.01, 0.01),
+        ];
+        let area = CorridorFilter::new(route, 50.0).unwrap().into();
+
+        // Around 33 m from the first segment
+        assert!(is_point_in_area(&area, &Coordinate::new(0.0003, 0.005)));
+        // Around 33 m from the second segment
+        assert!(is_point_in_area(&area, &Coordinate::new(0.005, 0.0103)));
+        // Around 67 m from the route
+        assert!(!is_point_in_area(&area, &Coordinate::new(0.0006, 0.005)));
+    }
+
+    #[test]
+    fn segment_crosses_box_without_points_inside() {
+        let area =
+            SquaredFilter::new(Coordinate::new(40.0, 40.0), Coordinate::new(50.0, 50.0)).into();
+
+        assert!(is_segment_in_area(
+            &area,
+            &Coordinate::new(45.0, 30.0),
+            &Coordinate::new(45.0, 60.0)
+        ));
+        // Cutting only the south-west corner
+        assert!(is_segment_in_area(
+            &area,
+            &Coordinate::new(38.0, 43.0),
+            &Coordinate::new(43.0, 38.0)
+        ));
+        assert!(!is_segment_in_area(
+            &area,
+            &Coordinate::new(38.0, 30.0),
+            &Coordinate::new(38.0, 60.0)
+        ));
+    }
+
+    #[test]
+    fn segment_crosses_box_across_antimeridian() {
+        let area = SquaredFilter::new(
+            Coordinate::new(-20.0, 179.0),
+            Coordinate::new(-10.0, -179.0),
+        )
+        .into();
+
+        // From 178 to -178 over the antimeridian, crossing the box
+        assert!(is_segment_in_area(
+            &area,
+            &Coordinate::new(-15.0, 178.0),
+            &Coordinate::new(-15.0, -178.0)
+        ));
+        // The same longitudes but south of the box
+        assert!(!is_segment_in_area(
+            &area,
+            &Coordinate::new(-25.0, 178.0),
+            &Coordinate::new(-25.0, -178.0)
+        ));
+    }
+
+    #[test]
+    fn segment_crosses_polygon() {
+        let area = polygon_with_hole();
+
+        // Through the tip of the triangle
+        assert!(is_segment_in_area(
+            &area,
+            &Coordinate::new(75.0, 50.0),
+            &Coordinate::new(75.0, 70.0)
+        ));
+        // Over the polygon, next to its tip
+        assert!(!is_segment_in_area(
+            &area,
+            &Coordinate::new(82.0, 50.0),
+            &Coordinate::new(82.0, 70.0)
+        ));
+        // Inside the hole
+        assert!(!is_segment_in_area(
+            &area,
+            &Coordinate::new(47.0, 59.0),
+            &Coordinate::new(47.0, 61.0)
+        ));
+        // From inside the hole to outside of it
+        assert!(is_segment_in_area(
+            &area,
+            &Coordinate::new(47.0, 59.0),
+            &Coordinate::new(52.0, 70.0)
+        ));
+    }
+
+    #[test]
+    fn segment_crosses_circle() {
+        let area = CircleFilter::new(Coordinate::new(0.0, 0.0), 500.0)
+            .unwrap()
+            .into();
+
+        assert!(is_segment_in_area(
+            &area,
+            &Coordinate::new(0.001, -0.01),
+            &Coordinate::new(0.001, 0.01)
+        ));
+        assert!(!is_segment_in_area(
+            &area,
+            &Coordinate::new(0.01, -0.01),
+            &Coordinate::new(0.01, 0.01)
+        ));
+    }
+
+    #[test]
+    fn percentage_of_track() {
+        let area = SquaredFilter::new(Coordinate::new(0.0, 0.0), Coordinate::new(1.0, 1.0)).into();
+        let point = |longitude: f32| TrackPoint::new(Coordinate::new(0.5, longitude), None);
+        let track = vec![point(0.2), point(0.4), point(0.6), point(1.2), point(1.4)];
+
+        // 0.4 degrees inside, 0.6 half inside and 0.2 outside
+        let percentage = percentage_of_track_in_area(&area, &track);
+        assert!((percentage - 58.33).abs() < 0.1, "{percentage}");
+
+        assert_eq!(100.0, percentage_of_track_in_area(&area, &track[..1]));
+        assert_eq!(0.0, percentage_of_track_in_area(&area, &track[4..]));
+    }
+
+    #[test]
+    fn visit_in_box() {
+        let area = SquaredFilter::new(Coordinate::new(0.0, 0.0), Coordinate::new(1.0, 1.0)).into();
+        let start: DateTime<Utc> = "2023-03-03T08:00:00Z".parse().unwrap();
+        let point = |longitude: f32, minutes: i64| {
+            TrackPoint::new(
+                Coordinate::new(0.5, longitude),
+                Some(start + Duration::minutes(minutes)),
+            )
+        };
+        let track = vec![
+            point(-0.2, 0),
+            point(0.2, 10),
+            point(0.4, 20),
+            point(1.2, 40),
+        ];
+
+        // 10 minutes half inside, 10 fully inside and 20 half inside
+        let visit = visit_in_area(&area, &track);
+        assert_eq!(Duration::minutes(25), visit.duration);
+        let length =
+            |a: usize| haversine_distance_in_m(&track[a].coordinate, &track[a + 1].coordinate);
+        let expected_m = length(0) / 2.0 + length(1) + length(2) / 2.0;
+        assert!((visit.distance_m - expected_m).abs() < 1.0, "{visit:?}");
+
+        // The jump from the end of a line to the start of the next one is not travelled
+        let lines = vec![
+            track[1],
+            TrackPoint {
+                line: 1,
+                ..track[2]
+            },
+        ];
+        assert_eq!(0.0, visit_in_area(&area, &lines).distance_m);
+    }
+
+    #[test]
+    fn distance_to_polygon() {
+        let point = Coordinate::new(60.0, 90.0);
+        let area = polygon_with_hole();
+
+        // 10 degrees east of the bounding box, around 553.9 km along the great circle
+        assert!(!is_point_more_than_x_distance_from_filter(
+            &area, &point, 554.0
+        ));
+        assert!(is_point_more_than_x_distance_from_filter(
+            &area, &point, 553.0
+        ));
+    }
 }